@@ -0,0 +1,154 @@
+//! Procedural macros for [drop_guard](https://docs.rs/drop_guard).
+//!
+//! Use them through the `macros` feature of drop_guard, which re-exports them.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Expr, Index, ItemFn, Local, Pat,
+    Path, Stmt,
+};
+
+/// Turns every `let` in the function that carries `#[cleanup(callback)]` into a guard.
+///
+/// See `drop_guard::cleanup` for the documentation.
+#[proc_macro_attribute]
+pub fn cleanup(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        let args = proc_macro2::TokenStream::from(args);
+        return syn::Error::new_spanned(
+            args,
+            "put `#[cleanup]` on the function and `#[cleanup(callback)]` on its `let` statements",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let mut item = parse_macro_input!(input as ItemFn);
+    let mut rewrite = Rewrite { errors: Vec::new() };
+    rewrite.visit_item_fn_mut(&mut item);
+
+    let errors = rewrite
+        .errors
+        .into_iter()
+        .map(syn::Error::into_compile_error);
+    quote!(#item #(#errors)*).into()
+}
+
+/// Rewrites the annotated `let` statements of a function body.
+struct Rewrite {
+    errors: Vec<syn::Error>,
+}
+
+fn is_cleanup(attr: &Attribute) -> bool {
+    attr.path().is_ident("cleanup")
+}
+
+impl Rewrite {
+    fn rewrite(&mut self, local: &mut Local) {
+        let at = match local.attrs.iter().position(is_cleanup) {
+            Some(at) => at,
+            None => return,
+        };
+        let attr = local.attrs.remove(at);
+        let callback: Expr = match attr.parse_args() {
+            Ok(callback) => callback,
+            Err(e) => return self.errors.push(e),
+        };
+        let init = match &mut local.init {
+            Some(init) if init.diverge.is_none() => init,
+            _ => {
+                return self.errors.push(syn::Error::new_spanned(
+                    &attr,
+                    "`#[cleanup]` needs a `let` with a value and without `else`",
+                ))
+            }
+        };
+
+        // the guard is accessed through `Deref`, so a type annotation moves to `guard::<T, _>`
+        let ty = match &mut local.pat {
+            Pat::Type(typed) => {
+                let ty = typed.ty.clone();
+                local.pat = (*typed.pat).clone();
+                Some(ty)
+            }
+            _ => None,
+        };
+        if let Pat::Ident(ident) = &mut local.pat {
+            ident.mutability = Some(Default::default());
+        } else {
+            return self.errors.push(syn::Error::new_spanned(
+                &local.pat,
+                "`#[cleanup]` needs a plain name like `let conn = ...`",
+            ));
+        }
+        local.attrs.push(parse_quote!(#[allow(unused_mut)]));
+
+        let value = &init.expr;
+        init.expr = match ty {
+            Some(ty) => parse_quote!(::drop_guard::guard::<#ty, _>(#value, #callback)),
+            None => parse_quote!(::drop_guard::guard(#value, #callback)),
+        };
+    }
+}
+
+impl VisitMut for Rewrite {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        if let Stmt::Local(local) = stmt {
+            self.rewrite(local);
+        }
+        visit_mut::visit_stmt_mut(self, stmt);
+    }
+}
+
+/// Implements `Drop` to run the `#[on_drop(path::to::cleanup)]` of each field in declared order.
+///
+/// See `drop_guard::Guarded` for the documentation.
+#[proc_macro_derive(Guarded, attributes(on_drop))]
+pub fn derive_guarded(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match guarded(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+fn guarded(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`Guarded` can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut guards = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("on_drop")) {
+            let cleanup: Path = attr.parse_args()?;
+            let member = match &field.ident {
+                Some(ident) => quote!(#ident),
+                None => {
+                    let index = Index::from(i);
+                    quote!(#index)
+                }
+            };
+            guards.push(quote!(::drop_guard::guard(&mut self.#member, #cleanup)));
+        }
+    }
+    // locals drop in reverse, so the first field is bound last, the guards keep running if one panics
+    guards.reverse();
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::core::ops::Drop for #name #ty_generics #where_clause {
+            fn drop(&mut self) {
+                #(let _guard = #guards;)*
+            }
+        }
+    })
+}
@@ -1,16 +1,13 @@
-use drop_guard::guard;
+use drop_guard::guard_spawn;
 
-use std::thread::{sleep, spawn};
+use std::thread::sleep;
 use std::time::Duration;
 
 fn main() {
-    let _ = guard(
-        spawn(move || {
-            sleep(Duration::from_secs(2));
-            println!("println! from thread");
-        }),
-        |join_handle| join_handle.join().unwrap(),
-    );
+    let _worker = guard_spawn(move || {
+        sleep(Duration::from_secs(2));
+        println!("println! from thread");
+    });
 
     println!("Waiting for thread ...");
 }
@@ -0,0 +1,172 @@
+//! Abort tasks and futures that run out of scope.
+
+#[cfg(feature = "tokio")]
+use crate::{async_guard, AsyncCleanup, AsyncDropGuard, Spawner};
+use crate::{guard, DropGuard};
+#[cfg(feature = "futures")]
+use futures_util::future::{AbortHandle, Abortable};
+#[cfg(feature = "tokio")]
+use tokio::task::JoinHandle;
+
+/// Aborts the task when the guard is dropped, the async twin of joining a thread on drop.
+///
+/// The guard is still a future, awaiting it waits for the task as usual.
+///
+/// ```
+/// use drop_guard::abort_guard;
+/// use std::time::Duration;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let heartbeat = abort_guard(tokio::spawn(async {
+///     loop {
+///         tokio::time::sleep(Duration::from_millis(10)).await;
+///     }
+/// }));
+/// let answer = abort_guard(tokio::spawn(async { 42 }));
+/// assert_eq!(42, answer.await.unwrap());
+/// // the heartbeat stops here
+/// # drop(heartbeat);
+/// # });
+/// ```
+#[cfg(feature = "tokio")]
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn abort_guard<R>(handle: JoinHandle<R>) -> DropGuard<JoinHandle<R>, fn(JoinHandle<R>)> {
+    guard(handle, |h| h.abort())
+}
+
+/// Aborts the task when the guard is dropped and waits with `spawner` until it stopped.
+///
+/// Use [`finish`](struct.AsyncDropGuard.html#method.finish) to wait for that in async code.
+/// The outcome of the task, a panic included, is discarded.
+///
+/// ```
+/// use drop_guard::abort_and_join;
+/// use tokio::runtime::Handle;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+/// let task = abort_and_join(Handle::current(), tokio::spawn(async move {
+///     std::future::pending::<()>().await;
+///     drop(tx);
+/// }));
+/// task.finish().await;
+/// // the task was dropped together with its sender
+/// assert!(rx.await.is_err());
+/// # });
+/// ```
+#[cfg(feature = "tokio")]
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn abort_and_join<R: Send + 'static, S: Spawner>(
+    spawner: S,
+    handle: JoinHandle<R>,
+) -> AsyncDropGuard<JoinHandle<R>, impl AsyncCleanup<JoinHandle<R>>, S> {
+    async_guard(spawner, handle, |h: JoinHandle<R>| async move {
+        h.abort();
+        let _ = h.await;
+    })
+}
+
+/// A guard aborting the future registered with its handle.
+#[cfg(feature = "futures")]
+pub type AbortOnDrop = DropGuard<AbortHandle, fn(AbortHandle)>;
+
+/// Aborts the future registered with `handle` when the guard is dropped.
+///
+/// Cancel the guard to let the future run on.
+#[cfg(feature = "futures")]
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_abort_handle(handle: AbortHandle) -> AbortOnDrop {
+    guard(handle, |h| h.abort())
+}
+
+/// Makes `fut` abortable and hands out a guard that aborts it unless cancelled.
+///
+/// ```
+/// use drop_guard::guard_abortable;
+/// use futures_util::FutureExt;
+///
+/// let (prefetch, abort) = guard_abortable(async { "warm cache" });
+/// {
+///     let _abort = abort;
+///     // ... the request failed, the prefetch is not needed anymore
+/// }
+/// assert!(prefetch.now_or_never().unwrap().is_err());
+///
+/// let (prefetch, abort) = guard_abortable(async { "warm cache" });
+/// abort.cancel();
+/// assert_eq!(Ok("warm cache"), prefetch.now_or_never().unwrap());
+/// ```
+#[cfg(feature = "futures")]
+#[track_caller]
+pub fn guard_abortable<Fut>(fut: Fut) -> (Abortable<Fut>, AbortOnDrop) {
+    let (handle, registration) = AbortHandle::new_pair();
+    (
+        Abortable::new(fut, registration),
+        guard_abort_handle(handle),
+    )
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use tokio::runtime::Handle;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn drop_aborts() {
+        let (tx, rx) = oneshot::channel::<()>();
+        {
+            let _task = abort_guard(tokio::spawn(async move {
+                std::future::pending::<()>().await;
+                drop(tx);
+            }));
+        }
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn finish_joins_the_aborted_task() {
+        let (tx, mut rx) = oneshot::channel::<()>();
+        let task = abort_and_join(
+            Handle::current(),
+            tokio::spawn(async move {
+                let _tx = tx;
+                std::future::pending::<()>().await;
+            }),
+        );
+        task.finish().await;
+        assert_eq!(Err(oneshot::error::TryRecvError::Closed), rx.try_recv());
+    }
+}
+
+#[cfg(all(test, feature = "futures"))]
+mod futures_tests {
+    use super::*;
+    use futures_util::future::{pending, Aborted};
+    use futures_util::FutureExt;
+
+    #[test]
+    fn aborted_after_being_polled() {
+        let (fut, abort) = guard_abortable(pending::<()>());
+        let mut fut = Box::pin(fut);
+        assert!((&mut fut).now_or_never().is_none());
+        drop(abort);
+        assert_eq!(Some(Err(Aborted)), fut.now_or_never());
+    }
+
+    #[test]
+    fn handle_from_elsewhere() {
+        let (handle, registration) = AbortHandle::new_pair();
+        let g = guard_abort_handle(handle.clone());
+        assert!(!handle.is_aborted());
+        drop(g);
+        assert!(handle.is_aborted());
+        drop(registration);
+    }
+}
@@ -0,0 +1,70 @@
+//! All or nothing acquisition of several guarded resources.
+
+use std::mem;
+
+/// Drops the acquired guards in reverse order, also while unwinding.
+struct Acquired<G>(Vec<G>);
+
+impl<G> Drop for Acquired<G> {
+    fn drop(&mut self) {
+        self.0.reverse();
+    }
+}
+
+/// Runs the acquirers in order and returns all guards, or none of them.
+///
+/// If an acquirer fails or panics, the guards acquired so far are dropped in reverse
+/// order, which releases them, before the error is returned.
+///
+/// ```
+/// use drop_guard::{acquire_all, guard};
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// let lock = |name: &'static str, ok: bool| {
+///     let log = &log;
+///     move || {
+///         if !ok {
+///             return Err(format!("{} is busy", name));
+///         }
+///         log.borrow_mut().push(format!("lock {}", name));
+///         Ok(guard(name, move |name| log.borrow_mut().push(format!("unlock {}", name))))
+///     }
+/// };
+///
+/// let result = acquire_all(vec![lock("a", true), lock("b", true), lock("c", false)]);
+/// assert_eq!(Some("c is busy".to_string()), result.err());
+/// assert_eq!(vec!["lock a", "lock b", "unlock b", "unlock a"], *log.borrow());
+/// ```
+pub fn acquire_all<G, E, I, A>(acquirers: I) -> Result<Vec<G>, E>
+where
+    I: IntoIterator<Item = A>,
+    A: FnOnce() -> Result<G, E>,
+{
+    let mut acquired = Acquired(Vec::new());
+    for acquire in acquirers {
+        acquired.0.push(acquire()?);
+    }
+    Ok(mem::take(&mut acquired.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard;
+    use std::cell::RefCell;
+
+    #[test]
+    fn keeps_all_on_success() {
+        let released = RefCell::new(Vec::new());
+        let acquirers = (0..3).map(|i| {
+            let released = &released;
+            move || Ok::<_, ()>(guard(i, move |i| released.borrow_mut().push(i)))
+        });
+        let guards = acquire_all(acquirers).unwrap();
+        assert_eq!(3, guards.len());
+        assert!(released.borrow().is_empty());
+        drop(guards);
+        assert_eq!(vec![0, 1, 2], *released.borrow());
+    }
+}
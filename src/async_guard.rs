@@ -0,0 +1,775 @@
+//! Guards with an async cleanup that is spawned on drop.
+
+use crate::report::report;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{deadline, timer::Timer};
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::future::poll_fn;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+#[cfg(not(target_arch = "wasm32"))]
+use std::pin::pin;
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::task::Poll;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// A boxed future that can be sent to another thread.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Runs futures in the background, implement it to use an async runtime with [`AsyncDropGuard`](struct.AsyncDropGuard.html).
+///
+/// The features `tokio`, `async-std`, `smol` and, on wasm, `wasm` provide implementations.
+///
+/// ```
+/// use drop_guard::{async_guard, BoxFuture, Spawner};
+/// use std::sync::mpsc;
+///
+/// /// Collects the futures to poll them later, e.g. in a test.
+/// struct Collect(mpsc::Sender<BoxFuture<'static, ()>>);
+///
+/// impl Spawner for Collect {
+///     fn spawn(&self, fut: BoxFuture<'static, ()>) {
+///         self.0.send(fut).unwrap();
+///     }
+/// }
+///
+/// let (tx, rx) = mpsc::channel();
+/// drop(async_guard(Collect(tx), 1, |_| async {}));
+/// assert_eq!(1, rx.try_iter().count());
+/// ```
+pub trait Spawner {
+    /// Starts `fut` in the background without waiting for it.
+    fn spawn(&self, fut: BoxFuture<'static, ()>);
+
+    /// The [`BlockOn`](trait.BlockOn.html) of the spawner, which
+    /// [`SyncDropStrategy::Block`](enum.SyncDropStrategy.html#variant.Block) needs.
+    ///
+    /// Spawners that implement `BlockOn` return `Some(self)`, the default is `None`.
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        None
+    }
+}
+
+impl<S: Spawner + ?Sized> Spawner for Arc<S> {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        (**self).spawn(fut)
+    }
+
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        (**self).as_block_on()
+    }
+}
+
+impl<S: Spawner + ?Sized> Spawner for &S {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        (**self).spawn(fut)
+    }
+
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        (**self).as_block_on()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawner for tokio::runtime::Handle {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        drop(tokio::runtime::Handle::spawn(self, fut));
+    }
+
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        Some(self)
+    }
+}
+
+/// Spawns on the global async-std runtime.
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+impl Spawner for AsyncStd {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        drop(async_std::task::spawn(fut));
+    }
+
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        Some(self)
+    }
+}
+
+/// Spawns on the global smol executor.
+#[cfg(feature = "smol")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Smol;
+
+#[cfg(feature = "smol")]
+impl Spawner for Smol {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        smol::spawn(fut).detach();
+    }
+
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        Some(self)
+    }
+}
+
+#[cfg(feature = "smol")]
+impl Spawner for smol::Executor<'static> {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        smol::Executor::spawn(self, fut).detach();
+    }
+
+    fn as_block_on(&self) -> Option<&dyn BlockOn> {
+        Some(self)
+    }
+}
+
+/// Spawns cleanups on the browser's event loop with `wasm_bindgen_futures::spawn_local`.
+///
+/// It is also a [`LocalSpawner`](trait.LocalSpawner.html), use it with
+/// [`local_async_guard`](fn.local_async_guard.html) for cleanups that await `!Send` browser futures.
+/// There is no [`BlockOn`](trait.BlockOn.html) for it, blocking is not allowed on wasm.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WasmLocal;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Spawner for WasmLocal {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        wasm_bindgen_futures::spawn_local(fut)
+    }
+}
+
+/// Runs a future to completion on the current thread.
+///
+/// The features `tokio`, `async-std`, `smol` and, on wasm, `wasm` provide implementations.
+pub trait BlockOn {
+    /// Blocks the current thread until `fut` is done.
+    fn block_on(&self, fut: BoxFuture<'_, ()>);
+
+    /// Whether [`block_on`](#tymethod.block_on) works on the current thread, `true` by default.
+    ///
+    /// A guard dropped where it does not spawns its cleanup instead of blocking.
+    fn can_block(&self) -> bool {
+        true
+    }
+}
+
+impl<B: BlockOn + ?Sized> BlockOn for Arc<B> {
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        (**self).block_on(fut)
+    }
+
+    fn can_block(&self) -> bool {
+        (**self).can_block()
+    }
+}
+
+impl<B: BlockOn + ?Sized> BlockOn for &B {
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        (**self).block_on(fut)
+    }
+
+    fn can_block(&self) -> bool {
+        (**self).can_block()
+    }
+}
+
+/// Panics when called from async code running on a Tokio runtime.
+#[cfg(feature = "tokio")]
+impl BlockOn for tokio::runtime::Handle {
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        tokio::runtime::Handle::block_on(self, fut)
+    }
+
+    /// `false` on threads of a Tokio runtime.
+    fn can_block(&self) -> bool {
+        tokio::runtime::Handle::try_current().is_err()
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl BlockOn for AsyncStd {
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        async_std::task::block_on(fut)
+    }
+}
+
+#[cfg(feature = "smol")]
+impl BlockOn for Smol {
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        smol::block_on(fut)
+    }
+}
+
+#[cfg(feature = "smol")]
+impl BlockOn for smol::Executor<'static> {
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        smol::block_on(self.run(fut))
+    }
+}
+
+/// An async cleanup of a `T`, implemented by every `FnOnce(T) -> impl Future<Output = ()>`.
+pub trait AsyncCleanup<T> {
+    /// The future that does the cleanup.
+    type Future: Future<Output = ()> + Send + 'static;
+
+    /// Starts the cleanup of `data`.
+    fn cleanup(self, data: T) -> Self::Future;
+}
+
+impl<T, F, Fut> AsyncCleanup<T> for F
+where
+    F: FnOnce(T) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    type Future = Fut;
+
+    fn cleanup(self, data: T) -> Fut {
+        self(data)
+    }
+}
+
+/// What an [`AsyncDropGuard`](struct.AsyncDropGuard.html) does with its cleanup when it is dropped without [`finish`](struct.AsyncDropGuard.html#method.finish).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncDropStrategy {
+    /// Spawns the cleanup and returns right away.
+    #[default]
+    Spawn,
+    /// Blocks the dropping thread until the cleanup is done, needs a [`BlockOn`](trait.BlockOn.html) spawner.
+    Block,
+    /// Skips the cleanup and logs it to stderr.
+    Skip,
+}
+
+/// Spawns an async cleanup with a [`Spawner`](trait.Spawner.html) when it is dropped.
+///
+/// Dropping it works in sync and in async code. The cleanup runs as a task of the runtime,
+/// so it is lost if the runtime shuts down first.
+/// To wait for the cleanup in async code, call [`finish`](#method.finish) instead of dropping it.
+pub struct AsyncDropGuard<T, F: AsyncCleanup<T>, S: Spawner> {
+    inner: Option<(T, F)>,
+    /// Only taken by `map_cleanup`, together with the cleanup.
+    spawner: Option<S>,
+    sync_drop: SyncDropStrategy,
+    /// Set by [`fallback_block`](#method.fallback_block) for spawners that do not override `as_block_on`.
+    block: Option<fn(&S, BoxFuture<'static, ()>)>,
+    pub(crate) location: &'static Location<'static>,
+}
+
+/// Creates an [`AsyncDropGuard`](struct.AsyncDropGuard.html) that spawns the cleanup with `spawner`.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn async_guard<T, F: AsyncCleanup<T>, S: Spawner>(
+    spawner: S,
+    data: T,
+    func: F,
+) -> AsyncDropGuard<T, F, S> {
+    AsyncDropGuard {
+        inner: Some((data, func)),
+        spawner: Some(spawner),
+        sync_drop: SyncDropStrategy::Spawn,
+        block: None,
+        location: Location::caller(),
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F: AsyncCleanup<T>> AsyncDropGuard<T, F, tokio::runtime::Handle> {
+    /// Spawns the cleanup right away and hands back its `JoinHandle` to wait for it.
+    ///
+    /// ```
+    /// use drop_guard::async_guard;
+    ///
+    /// let rt = tokio::runtime::Runtime::new().unwrap();
+    /// let (tx, rx) = tokio::sync::oneshot::channel();
+    /// {
+    ///     let _lease = async_guard(rt.handle().clone(), "lease-7", |lease| async move {
+    ///         // release the lease with an async API call ...
+    ///         tx.send(lease).unwrap();
+    ///     });
+    /// }
+    /// assert_eq!(Ok("lease-7"), rt.block_on(rx));
+    ///
+    /// let lease = async_guard(rt.handle().clone(), "lease-8", |_| async {});
+    /// rt.block_on(lease.spawn_now()).unwrap();
+    /// ```
+    pub fn spawn_now(mut self) -> tokio::task::JoinHandle<()> {
+        let (data, func) = self.take();
+        self.spawner().spawn(func.cleanup(data))
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl<T, F: AsyncCleanup<T>> AsyncDropGuard<T, F, AsyncStd> {
+    /// Spawns the cleanup right away and hands back its `JoinHandle` to wait for it.
+    ///
+    /// ```
+    /// use drop_guard::{async_guard, AsyncStd};
+    ///
+    /// let conn = async_guard(AsyncStd, "conn-1", |_| async { /* close */ });
+    /// async_std::task::block_on(conn.spawn_now());
+    /// ```
+    pub fn spawn_now(mut self) -> async_std::task::JoinHandle<()> {
+        let (data, func) = self.take();
+        async_std::task::spawn(func.cleanup(data))
+    }
+}
+
+#[cfg(feature = "smol")]
+impl<T, F: AsyncCleanup<T>> AsyncDropGuard<T, F, Smol> {
+    /// Spawns the cleanup right away and hands back its `Task` to wait for it.
+    ///
+    /// ```
+    /// use drop_guard::{async_guard, Smol};
+    ///
+    /// let conn = async_guard(Smol, "conn-1", |_| async { /* close */ });
+    /// smol::block_on(conn.spawn_now());
+    /// ```
+    pub fn spawn_now(mut self) -> smol::Task<()> {
+        let (data, func) = self.take();
+        smol::spawn(func.cleanup(data))
+    }
+}
+
+impl<T, F: AsyncCleanup<T>, S: Spawner + BlockOn> AsyncDropGuard<T, F, S> {
+    /// Runs the cleanup on the current thread and waits until it is done.
+    ///
+    /// Use it in sync code, e.g. at the end of `main` when no runtime drives the tasks any more.
+    pub fn block_now(mut self) {
+        let (data, func) = self.take();
+        self.spawner().block_on(Box::pin(func.cleanup(data)))
+    }
+
+    /// Blocks the dropping thread until the cleanup is done if the guard is dropped without [`finish`](#method.finish).
+    ///
+    /// Where the spawner [cannot block](trait.BlockOn.html#method.can_block), e.g. a guard
+    /// with a Tokio `Handle` dropped on a thread of the runtime, the cleanup is spawned instead.
+    #[must_use]
+    pub fn fallback_block(mut self) -> Self {
+        self.sync_drop = SyncDropStrategy::Block;
+        self.block = Some(|spawner, fut| {
+            if spawner.can_block() {
+                spawner.block_on(fut)
+            } else {
+                spawner.spawn(fut)
+            }
+        });
+        self
+    }
+}
+
+impl<T, F: AsyncCleanup<T>, S: Spawner> AsyncDropGuard<T, F, S> {
+    /// Runs the cleanup in place and waits for it, the deterministic way to end the guard in async code.
+    ///
+    /// ```
+    /// use drop_guard::{async_guard, BoxFuture, Spawner};
+    /// use std::future::Future;
+    /// use std::sync::mpsc;
+    /// use std::task::{Context, Poll, Waker};
+    ///
+    /// struct NoSpawn;
+    ///
+    /// impl Spawner for NoSpawn {
+    ///     fn spawn(&self, _: BoxFuture<'static, ()>) {
+    ///         unreachable!("a finished guard spawns nothing")
+    ///     }
+    /// }
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let conn = async_guard(NoSpawn, "conn-1", move |c| async move { tx.send(c).unwrap() });
+    ///
+    /// let mut finish = Box::pin(conn.finish());
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Poll::Ready(()), finish.as_mut().poll(&mut cx));
+    /// assert_eq!(Ok("conn-1"), rx.try_recv());
+    /// ```
+    pub async fn finish(mut self) {
+        let (data, func) = self.take();
+        func.cleanup(data).await
+    }
+
+    /// Spawns the cleanup if the guard is dropped without [`finish`](#method.finish), the default.
+    #[must_use]
+    pub fn fallback_spawn(mut self) -> Self {
+        self.sync_drop = SyncDropStrategy::Spawn;
+        self
+    }
+
+    /// Skips the cleanup and logs it to stderr if the guard is dropped without [`finish`](#method.finish).
+    #[must_use]
+    pub fn fallback_skip(mut self) -> Self {
+        self.sync_drop = SyncDropStrategy::Skip;
+        self
+    }
+
+    /// Chooses what happens to the cleanup if the guard is dropped without [`finish`](#method.finish).
+    ///
+    /// Works with every spawner, `Block` needs one whose
+    /// [`as_block_on`](trait.Spawner.html#method.as_block_on) returns its [`BlockOn`](trait.BlockOn.html).
+    ///
+    /// ```
+    /// use drop_guard::{async_guard, BlockOn, BoxFuture, Spawner, SyncDropStrategy};
+    /// use std::future::Future;
+    /// use std::sync::mpsc;
+    /// use std::task::{Context, Waker};
+    ///
+    /// struct Inline;
+    ///
+    /// impl Spawner for Inline {
+    ///     fn spawn(&self, _: BoxFuture<'static, ()>) {
+    ///         unreachable!("this guard blocks")
+    ///     }
+    ///
+    ///     fn as_block_on(&self) -> Option<&dyn BlockOn> {
+    ///         Some(self)
+    ///     }
+    /// }
+    ///
+    /// impl BlockOn for Inline {
+    ///     fn block_on(&self, mut fut: BoxFuture<'_, ()>) {
+    ///         // enough for cleanups that never wait
+    ///         assert!(fut.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_ready());
+    ///     }
+    /// }
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let flush = async_guard(Inline, "buffer", move |b| async move { tx.send(b).unwrap() })
+    ///     .with_sync_drop(SyncDropStrategy::Block);
+    /// assert_eq!(SyncDropStrategy::Block, flush.sync_drop_strategy());
+    /// drop(flush);
+    /// assert_eq!(Ok("buffer"), rx.try_recv());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `strategy` is `Block` and the spawner has no `BlockOn`.
+    #[must_use]
+    #[track_caller]
+    pub fn with_sync_drop(mut self, strategy: SyncDropStrategy) -> Self {
+        assert!(
+            strategy != SyncDropStrategy::Block
+                || self.block.is_some()
+                || self.spawner().as_block_on().is_some(),
+            "SyncDropStrategy::Block needs a spawner with BlockOn"
+        );
+        self.sync_drop = strategy;
+        self
+    }
+
+    /// What happens to the cleanup if the guard is dropped without [`finish`](#method.finish).
+    pub fn sync_drop_strategy(&self) -> SyncDropStrategy {
+        self.sync_drop
+    }
+
+    /// Swaps the cleanup for one built from it, keeping everything else.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub(crate) fn map_cleanup<G, M>(mut self, map: M) -> AsyncDropGuard<T, G, S>
+    where
+        G: AsyncCleanup<T>,
+        M: FnOnce(F) -> G,
+    {
+        let (data, func) = self.take();
+        AsyncDropGuard {
+            inner: Some((data, map(func))),
+            spawner: self.spawner.take(),
+            sync_drop: self.sync_drop,
+            block: self.block,
+            location: self.location,
+        }
+    }
+
+    /// Takes the value out without running the cleanup.
+    pub fn into_inner(mut self) -> T {
+        self.take().0
+    }
+
+    fn take(&mut self) -> (T, F) {
+        self.inner.take().expect("the guard is only emptied once")
+    }
+
+    fn spawner(&self) -> &S {
+        self.spawner
+            .as_ref()
+            .expect("the spawner is only taken with the cleanup")
+    }
+
+    fn parts(&self) -> &(T, F) {
+        self.inner.as_ref().expect("the guard is only emptied once")
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T, F: AsyncCleanup<T>, S: Spawner> AsyncDropGuard<T, F, S> {
+    /// Gives up on the cleanup after `budget`, so a stuck cleanup does not pile up on the runtime.
+    ///
+    /// The cleanup future is dropped when the time is up and the
+    /// [`DeadlineHandler`](type.DeadlineHandler.html) is told. All timeouts share one timer thread,
+    /// so it works with every [`Spawner`](trait.Spawner.html).
+    ///
+    /// ```
+    /// use drop_guard::{async_guard, BoxFuture, Spawner};
+    /// use std::future::{pending, Future};
+    /// use std::sync::mpsc;
+    /// use std::task::{Context, Waker};
+    /// use std::time::Duration;
+    ///
+    /// struct Collect(mpsc::Sender<BoxFuture<'static, ()>>);
+    ///
+    /// impl Spawner for Collect {
+    ///     fn spawn(&self, fut: BoxFuture<'static, ()>) {
+    ///         self.0.send(fut).unwrap();
+    ///     }
+    /// }
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// drop(async_guard(Collect(tx), "conn-2", |_| pending()).with_timeout(Duration::from_millis(10)));
+    ///
+    /// // drive the cleanup like a runtime would, it ends although it never completes
+    /// let mut cleanup = rx.recv().unwrap();
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// while cleanup.as_mut().poll(&mut cx).is_pending() {
+    ///     std::thread::sleep(Duration::from_millis(1));
+    /// }
+    /// ```
+    #[must_use]
+    pub fn with_timeout(self, budget: Duration) -> AsyncDropGuard<T, impl AsyncCleanup<T>, S> {
+        let location = self.location;
+        self.map_cleanup(move |func| {
+            move |data| {
+                let cleanup = func.cleanup(data);
+                async move {
+                    let timer = Timer::start(budget);
+                    let mut cleanup = pin!(cleanup);
+                    let finished = poll_fn(|cx| match cleanup.as_mut().poll(cx) {
+                        Poll::Ready(()) => Poll::Ready(true),
+                        Poll::Pending => timer.poll_elapsed(cx).map(|()| false),
+                    })
+                    .await;
+                    if !finished {
+                        deadline::report(budget, location);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl<T, F: AsyncCleanup<T>, S: Spawner> Deref for AsyncDropGuard<T, F, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.parts().0
+    }
+}
+
+impl<T, F: AsyncCleanup<T>, S: Spawner> DerefMut for AsyncDropGuard<T, F, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self
+            .inner
+            .as_mut()
+            .expect("the guard is only emptied once")
+            .0
+    }
+}
+
+impl<T: fmt::Debug, F: AsyncCleanup<T>, S: Spawner> fmt::Debug for AsyncDropGuard<T, F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncDropGuard")
+            .field("data", &self.parts().0)
+            .finish()
+    }
+}
+
+impl<T, F: AsyncCleanup<T>, S: Spawner> Drop for AsyncDropGuard<T, F, S> {
+    fn drop(&mut self) {
+        if let Some((data, func)) = self.inner.take() {
+            let fut = Box::pin(func.cleanup(data));
+            let spawner = self.spawner();
+            match (self.sync_drop, self.block) {
+                (SyncDropStrategy::Block, Some(block)) => block(spawner, fut),
+                (SyncDropStrategy::Block, None) => match spawner.as_block_on() {
+                    Some(blocker) if blocker.can_block() => blocker.block_on(fut),
+                    _ => spawner.spawn(fut),
+                },
+                (SyncDropStrategy::Skip, _) => {
+                    drop(fut);
+                    report(format_args!(
+                        "the AsyncDropGuard created at {} was dropped without finish(), its cleanup is skipped",
+                        self.location
+                    ));
+                }
+                _ => spawner.spawn(fut),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use tokio::runtime::Handle;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn finish_waits() {
+        let (tx, mut rx) = oneshot::channel();
+        let g = async_guard(Handle::current(), 6, |v| async move {
+            tokio::task::yield_now().await;
+            tx.send(v).unwrap();
+        });
+        g.finish().await;
+        assert_eq!(Ok(6), rx.try_recv());
+    }
+
+    #[tokio::test]
+    async fn drop_in_async_code() {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut g = async_guard(Handle::current(), 1, |v| async move {
+                tx.send(v).unwrap();
+            });
+            *g += 1;
+        }
+        assert_eq!(Ok(2), rx.await);
+    }
+
+    #[tokio::test]
+    async fn spawn_now_and_into_inner() {
+        let (tx, rx) = oneshot::channel();
+        let g = async_guard(Handle::current(), 3, |v| async move {
+            tx.send(v).unwrap();
+        });
+        g.spawn_now().await.unwrap();
+        assert_eq!(Ok(3), rx.await);
+
+        let g = async_guard(Handle::current(), 4, |_| async { unreachable!() });
+        assert_eq!(4, g.into_inner());
+    }
+
+    #[test]
+    fn block_now_waits() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let g = async_guard(rt.handle().clone(), 5, |v| async move {
+            tokio::task::yield_now().await;
+            tx.send(v).unwrap();
+        });
+        g.block_now();
+        assert_eq!(Ok(5), rx.try_recv());
+    }
+
+    #[tokio::test]
+    async fn fallback_block_spawns_on_runtime_threads() {
+        let (tx, rx) = oneshot::channel();
+        drop(
+            async_guard(Handle::current(), 8, |v| async move {
+                tx.send(v).unwrap();
+            })
+            .fallback_block(),
+        );
+        assert_eq!(Ok(8), rx.await);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn timeout_gives_up() {
+        let start = std::time::Instant::now();
+        let g = async_guard(Handle::current(), (), |_| std::future::pending())
+            .with_timeout(Duration::from_millis(20));
+        g.finish().await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn timeout_lets_fast_cleanups_finish() {
+        let (tx, rx) = oneshot::channel();
+        async_guard(Handle::current(), 9, |v| async move { tx.send(v).unwrap() })
+            .with_timeout(Duration::from_secs(60))
+            .finish()
+            .await;
+        assert_eq!(Ok(9), rx.await);
+    }
+}
+
+#[cfg(test)]
+mod fallback_tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Keeps the spawned futures and blocks by dropping them, enough to see which path ran.
+    #[derive(Default)]
+    struct Record(RefCell<Vec<&'static str>>);
+
+    impl Spawner for Record {
+        fn spawn(&self, _: BoxFuture<'static, ()>) {
+            self.0.borrow_mut().push("spawn");
+        }
+
+        fn as_block_on(&self) -> Option<&dyn BlockOn> {
+            Some(self)
+        }
+    }
+
+    impl BlockOn for Record {
+        fn block_on(&self, _: BoxFuture<'_, ()>) {
+            self.0.borrow_mut().push("block");
+        }
+    }
+
+    #[test]
+    fn fallbacks() {
+        let record = Record::default();
+        drop(async_guard(&record, (), |_| async {}));
+        drop(async_guard(&record, (), |_| async {}).fallback_block());
+        drop(async_guard(&record, (), |_| async {}).fallback_skip());
+        drop(
+            async_guard(&record, (), |_| async {})
+                .fallback_skip()
+                .fallback_spawn(),
+        );
+        drop(async_guard(&record, (), |_| async {}).with_sync_drop(SyncDropStrategy::Skip));
+        drop(async_guard(&record, (), |_| async {}).with_sync_drop(SyncDropStrategy::Block));
+        assert_eq!(vec!["spawn", "block", "spawn", "block"], *record.0.borrow());
+    }
+
+    struct SpawnOnly;
+
+    impl Spawner for SpawnOnly {
+        fn spawn(&self, _: BoxFuture<'static, ()>) {}
+    }
+
+    #[test]
+    fn sync_drop_without_block_on() {
+        let g = async_guard(SpawnOnly, (), |_| async {}).with_sync_drop(SyncDropStrategy::Skip);
+        assert_eq!(SyncDropStrategy::Skip, g.sync_drop_strategy());
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a spawner with BlockOn")]
+    fn block_needs_block_on() {
+        drop(async_guard(SpawnOnly, (), |_| async {}).with_sync_drop(SyncDropStrategy::Block));
+    }
+}
+
+#[cfg(all(test, feature = "async-std", feature = "smol"))]
+mod parity_tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn async_std_and_smol() {
+        let (tx, rx) = mpsc::channel();
+        let tx2 = tx.clone();
+        async_guard(AsyncStd, 1, |v| async move { tx.send(v).unwrap() }).block_now();
+        async_guard(Smol, 2, |v| async move { tx2.send(v).unwrap() }).block_now();
+        assert_eq!(vec![1, 2], rx.try_iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,206 @@
+//! Guards with an async cleanup that stays on the current thread, for `!Send` futures.
+
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// A boxed future that stays on the thread that created it.
+pub type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Runs futures in the background on the current thread, implement it for runtimes whose futures are not `Send`.
+///
+/// On wasm the `wasm` feature implements it for [`WasmLocal`](struct.WasmLocal.html),
+/// so cleanups can await browser futures such as `JsFuture`.
+pub trait LocalSpawner {
+    /// Starts `fut` on the current thread without waiting for it.
+    fn spawn_local(&self, fut: LocalBoxFuture<'static, ()>);
+}
+
+impl<S: LocalSpawner + ?Sized> LocalSpawner for Rc<S> {
+    fn spawn_local(&self, fut: LocalBoxFuture<'static, ()>) {
+        (**self).spawn_local(fut)
+    }
+}
+
+impl<S: LocalSpawner + ?Sized> LocalSpawner for &S {
+    fn spawn_local(&self, fut: LocalBoxFuture<'static, ()>) {
+        (**self).spawn_local(fut)
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl LocalSpawner for crate::WasmLocal {
+    fn spawn_local(&self, fut: LocalBoxFuture<'static, ()>) {
+        wasm_bindgen_futures::spawn_local(fut)
+    }
+}
+
+/// An async cleanup of a `T` whose future may not be `Send`, implemented by every `FnOnce(T) -> impl Future<Output = ()>`.
+pub trait LocalAsyncCleanup<T> {
+    /// The future that does the cleanup.
+    type Future: Future<Output = ()> + 'static;
+
+    /// Starts the cleanup of `data`.
+    fn cleanup(self, data: T) -> Self::Future;
+}
+
+impl<T, F, Fut> LocalAsyncCleanup<T> for F
+where
+    F: FnOnce(T) -> Fut,
+    Fut: Future<Output = ()> + 'static,
+{
+    type Future = Fut;
+
+    fn cleanup(self, data: T) -> Fut {
+        self(data)
+    }
+}
+
+/// Spawns an async cleanup with a [`LocalSpawner`](trait.LocalSpawner.html) when it is dropped.
+///
+/// The counterpart of [`AsyncDropGuard`](struct.AsyncDropGuard.html) for cleanups that
+/// have to stay on the current thread. Call [`finish`](#method.finish) to wait for the cleanup.
+pub struct LocalAsyncDropGuard<T, F: LocalAsyncCleanup<T>, S: LocalSpawner> {
+    inner: Option<(T, F)>,
+    spawner: S,
+}
+
+/// Creates a [`LocalAsyncDropGuard`](struct.LocalAsyncDropGuard.html) that spawns the cleanup with `spawner`.
+///
+/// ```
+/// use drop_guard::{local_async_guard, LocalBoxFuture, LocalSpawner};
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// /// Collects the futures to poll them later, e.g. in a test.
+/// #[derive(Default)]
+/// struct Collect(RefCell<Vec<LocalBoxFuture<'static, ()>>>);
+///
+/// impl LocalSpawner for Collect {
+///     fn spawn_local(&self, fut: LocalBoxFuture<'static, ()>) {
+///         self.0.borrow_mut().push(fut);
+///     }
+/// }
+///
+/// let spawner = Collect::default();
+/// // an `Rc` makes the cleanup `!Send`, like the futures of a browser API
+/// let closed = Rc::new(RefCell::new(false));
+/// let flag = closed.clone();
+/// drop(local_async_guard(&spawner, "socket", move |_| async move {
+///     *flag.borrow_mut() = true;
+/// }));
+/// assert_eq!(1, spawner.0.borrow().len());
+/// ```
+#[must_use]
+#[inline]
+pub fn local_async_guard<T, F: LocalAsyncCleanup<T>, S: LocalSpawner>(
+    spawner: S,
+    data: T,
+    func: F,
+) -> LocalAsyncDropGuard<T, F, S> {
+    LocalAsyncDropGuard {
+        inner: Some((data, func)),
+        spawner,
+    }
+}
+
+impl<T, F: LocalAsyncCleanup<T>, S: LocalSpawner> LocalAsyncDropGuard<T, F, S> {
+    /// Runs the cleanup in place and waits for it.
+    pub async fn finish(mut self) {
+        let (data, func) = self.take();
+        func.cleanup(data).await
+    }
+
+    /// Takes the value out without running the cleanup.
+    pub fn into_inner(mut self) -> T {
+        self.take().0
+    }
+
+    fn take(&mut self) -> (T, F) {
+        self.inner.take().expect("the guard is only emptied once")
+    }
+
+    fn parts(&self) -> &(T, F) {
+        self.inner.as_ref().expect("the guard is only emptied once")
+    }
+}
+
+impl<T, F: LocalAsyncCleanup<T>, S: LocalSpawner> Deref for LocalAsyncDropGuard<T, F, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.parts().0
+    }
+}
+
+impl<T, F: LocalAsyncCleanup<T>, S: LocalSpawner> DerefMut for LocalAsyncDropGuard<T, F, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self
+            .inner
+            .as_mut()
+            .expect("the guard is only emptied once")
+            .0
+    }
+}
+
+impl<T: fmt::Debug, F: LocalAsyncCleanup<T>, S: LocalSpawner> fmt::Debug
+    for LocalAsyncDropGuard<T, F, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalAsyncDropGuard")
+            .field("data", &self.parts().0)
+            .finish()
+    }
+}
+
+impl<T, F: LocalAsyncCleanup<T>, S: LocalSpawner> Drop for LocalAsyncDropGuard<T, F, S> {
+    fn drop(&mut self) {
+        if let Some((data, func)) = self.inner.take() {
+            self.spawner.spawn_local(Box::pin(func.cleanup(data)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::task::{Context, Poll, Waker};
+
+    #[derive(Default)]
+    struct Collect(RefCell<Vec<LocalBoxFuture<'static, ()>>>);
+
+    impl LocalSpawner for Collect {
+        fn spawn_local(&self, fut: LocalBoxFuture<'static, ()>) {
+            self.0.borrow_mut().push(fut);
+        }
+    }
+
+    #[test]
+    fn spawns_and_finishes_local_cleanups() {
+        let spawner = Collect::default();
+        let done = Rc::new(Cell::new(0));
+
+        let counter = done.clone();
+        let mut g = local_async_guard(&spawner, 1, move |v| async move {
+            counter.set(counter.get() + v);
+        });
+        *g += 1;
+        drop(g);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut spawned = spawner.0.borrow_mut().pop().unwrap();
+        assert_eq!(Poll::Ready(()), spawned.as_mut().poll(&mut cx));
+        assert_eq!(2, done.get());
+
+        let counter = done.clone();
+        let g = local_async_guard(&spawner, 5, move |v| async move {
+            counter.set(counter.get() + v);
+        });
+        let mut finish = Box::pin(g.finish());
+        assert_eq!(Poll::Ready(()), finish.as_mut().poll(&mut cx));
+        assert_eq!(7, done.get());
+        assert!(spawner.0.borrow().is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! Async cleanups that are awaited at the end of an async block.
+
+use crate::async_transaction::{boxed, roll_back, Step};
+use crate::{async_guard, BoxFuture, Spawner};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Registers cleanups for the end of an [`async_scope`](fn.async_scope.html).
+///
+/// Clones register with the same scope, so it can be moved into spawned tasks.
+#[derive(Clone, Default)]
+pub struct AsyncScope {
+    cleanups: Arc<Mutex<Vec<Step>>>,
+}
+
+impl AsyncScope {
+    /// Registers an async cleanup, it runs after the body before the cleanups registered earlier.
+    ///
+    /// Cleanups registered after the scope ended are dropped without running.
+    pub fn defer<F, Fut>(&self, cleanup: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.cleanups
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(boxed(cleanup));
+    }
+
+    fn take(&self) -> Vec<Step> {
+        std::mem::take(&mut *self.cleanups.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl fmt::Debug for AsyncScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cleanups = self
+            .cleanups
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len();
+        f.debug_struct("AsyncScope")
+            .field("cleanups", &cleanups)
+            .finish()
+    }
+}
+
+fn run_cleanups(scope: AsyncScope) -> BoxFuture<'static, ()> {
+    roll_back(scope.take())
+}
+
+/// Runs the future built by `body` and then awaits the cleanups it registered on the scope.
+///
+/// A plain guard in an async block can not await its cleanup. Here the cleanups run on the
+/// current task once the body is done, the last registered first. If the scope is cancelled
+/// at an await point or the body panics, they are handed to `spawner` instead.
+///
+/// ```
+/// use drop_guard::{async_scope, BoxFuture, Spawner};
+/// use std::future::Future;
+/// use std::sync::{Arc, Mutex};
+/// use std::task::{Context, Poll, Waker};
+///
+/// struct NoSpawn;
+///
+/// impl Spawner for NoSpawn {
+///     fn spawn(&self, _: BoxFuture<'static, ()>) {
+///         unreachable!("the scope completes")
+///     }
+/// }
+///
+/// let log = Arc::new(Mutex::new(Vec::new()));
+/// let l = log.clone();
+/// let request = async_scope(NoSpawn, |scope| async move {
+///     let conn = "conn-3";
+///     let cleanup_log = l.clone();
+///     scope.defer(move || async move { cleanup_log.lock().unwrap().push("closed conn-3") });
+///     l.lock().unwrap().push("sent request");
+///     conn.len()
+/// });
+///
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert_eq!(Poll::Ready(6), Box::pin(request).as_mut().poll(&mut cx));
+/// assert_eq!(vec!["sent request", "closed conn-3"], *log.lock().unwrap());
+/// ```
+#[track_caller]
+pub fn async_scope<S, B, Fut>(spawner: S, body: B) -> impl Future<Output = Fut::Output>
+where
+    S: Spawner,
+    B: FnOnce(AsyncScope) -> Fut,
+    Fut: Future,
+{
+    let scope = AsyncScope::default();
+    let cleanups = async_guard(spawner, scope.clone(), run_cleanups);
+    async move {
+        let output = body(scope).await;
+        cleanups.finish().await;
+        output
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Handle;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn cleanups_run_on_cancel() {
+        let (closed, was_closed) = oneshot::channel();
+        let scope = async_scope(Handle::current(), |scope| async move {
+            scope.defer(move || async move { closed.send(()).unwrap() });
+            std::future::pending::<()>().await
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(10), scope)
+            .await
+            .is_err());
+        was_closed.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cleanups_from_spawned_tasks() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let l = log.clone();
+        async_scope(Handle::current(), |scope| async move {
+            let child = scope.clone();
+            let l2 = l.clone();
+            tokio::spawn(async move {
+                child.defer(move || async move { l2.lock().unwrap().push("child") });
+            })
+            .await
+            .unwrap();
+            scope.defer(move || async move { l.lock().unwrap().push("parent") });
+        })
+        .await;
+        assert_eq!(vec!["parent", "child"], *log.lock().unwrap());
+    }
+}
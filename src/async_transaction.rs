@@ -0,0 +1,223 @@
+//! Transactions whose rollbacks and commit hooks are async.
+
+use crate::{async_guard, AsyncDropGuard, BlockOn, BoxFuture, Spawner, SyncDropStrategy};
+use std::fmt;
+use std::future::Future;
+
+/// An async step registered ahead of time, started when it is due.
+pub(crate) type Step = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Starts the rollbacks once the transaction is dropped or rolled back.
+type RollBack = fn(Vec<Step>) -> BoxFuture<'static, ()>;
+
+pub(crate) fn boxed<F, Fut>(f: F) -> Step
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Box::new(move || Box::pin(f()))
+}
+
+/// Runs the rollbacks one after the other, the last registered first.
+pub(crate) fn roll_back(rollbacks: Vec<Step>) -> BoxFuture<'static, ()> {
+    Box::pin(async move {
+        for rollback in rollbacks.into_iter().rev() {
+            rollback().await;
+        }
+    })
+}
+
+/// Collects async rollbacks and commit hooks, await [`commit`](#method.commit) or [`rollback`](#method.rollback) to end it.
+///
+/// If it is dropped instead, the rollbacks are handed to the spawner like the cleanup of an
+/// [`AsyncDropGuard`](struct.AsyncDropGuard.html), see [`fallback_block`](#method.fallback_block)
+/// and [`fallback_skip`](#method.fallback_skip) for the alternatives.
+///
+/// ```
+/// use drop_guard::{AsyncTransaction, BoxFuture, Spawner};
+/// use std::future::Future;
+/// use std::sync::{Arc, Mutex};
+/// use std::task::{Context, Poll, Waker};
+///
+/// struct NoSpawn;
+///
+/// impl Spawner for NoSpawn {
+///     fn spawn(&self, _: BoxFuture<'static, ()>) {
+///         unreachable!("the transaction is ended explicitly")
+///     }
+/// }
+///
+/// let log = Arc::new(Mutex::new(Vec::new()));
+/// let order = async {
+///     let mut tx = AsyncTransaction::new(NoSpawn);
+///     let l = log.clone();
+///     tx.defer_rollback(move || async move { l.lock().unwrap().push("delete row") });
+///     let l = log.clone();
+///     tx.defer_rollback(move || async move { l.lock().unwrap().push("cancel payment") });
+///     // ... the HTTP call failed
+///     tx.rollback().await;
+/// };
+///
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert_eq!(Poll::Ready(()), Box::pin(order).as_mut().poll(&mut cx));
+/// assert_eq!(vec!["cancel payment", "delete row"], *log.lock().unwrap());
+/// ```
+pub struct AsyncTransaction<S: Spawner> {
+    rollbacks: AsyncDropGuard<Vec<Step>, RollBack, S>,
+    commit_hooks: Vec<Step>,
+}
+
+impl<S: Spawner> AsyncTransaction<S> {
+    /// Starts an empty transaction that spawns its rollbacks with `spawner` if it is dropped.
+    #[must_use]
+    #[track_caller]
+    pub fn new(spawner: S) -> Self {
+        AsyncTransaction {
+            rollbacks: async_guard(spawner, Vec::new(), roll_back),
+            commit_hooks: Vec::new(),
+        }
+    }
+
+    /// Registers an async compensation for a step that succeeded.
+    pub fn defer_rollback<F, Fut>(&mut self, rollback: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.rollbacks.push(boxed(rollback));
+    }
+
+    /// Registers an async hook that only runs on [`commit`](#method.commit), after the hooks added before it.
+    pub fn on_commit<F, Fut>(&mut self, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.commit_hooks.push(boxed(hook));
+    }
+
+    /// The number of registered rollbacks.
+    pub fn len(&self) -> usize {
+        self.rollbacks.len()
+    }
+
+    /// Whether no rollback is registered.
+    pub fn is_empty(&self) -> bool {
+        self.rollbacks.is_empty()
+    }
+
+    /// Drops the rollbacks and runs the commit hooks in order on the current task.
+    pub async fn commit(self) {
+        drop(self.rollbacks.into_inner());
+        for hook in self.commit_hooks {
+            hook().await;
+        }
+    }
+
+    /// Runs the rollbacks in reverse order on the current task, the commit hooks are dropped.
+    pub async fn rollback(self) {
+        self.rollbacks.finish().await
+    }
+
+    /// Spawns the rollbacks if the transaction is dropped without being ended, the default.
+    #[must_use]
+    pub fn fallback_spawn(mut self) -> Self {
+        self.rollbacks = self.rollbacks.fallback_spawn();
+        self
+    }
+
+    /// Skips the rollbacks and logs it to stderr if the transaction is dropped without being ended.
+    #[must_use]
+    pub fn fallback_skip(mut self) -> Self {
+        self.rollbacks = self.rollbacks.fallback_skip();
+        self
+    }
+
+    /// Chooses what happens to the rollbacks if the transaction is dropped without being ended.
+    ///
+    /// # Panics
+    ///
+    /// If `strategy` is `Block` and the spawner has no [`BlockOn`](trait.BlockOn.html).
+    #[must_use]
+    #[track_caller]
+    pub fn with_sync_drop(mut self, strategy: SyncDropStrategy) -> Self {
+        self.rollbacks = self.rollbacks.with_sync_drop(strategy);
+        self
+    }
+}
+
+impl<S: Spawner + BlockOn> AsyncTransaction<S> {
+    /// Blocks the dropping thread until the rollbacks are done if the transaction is dropped without being ended.
+    #[must_use]
+    pub fn fallback_block(mut self) -> Self {
+        self.rollbacks = self.rollbacks.fallback_block();
+        self
+    }
+}
+
+impl<S: Spawner> fmt::Debug for AsyncTransaction<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncTransaction")
+            .field("rollbacks", &self.rollbacks.len())
+            .field("commit_hooks", &self.commit_hooks.len())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::runtime::Handle;
+
+    fn logger(
+        log: &Arc<Mutex<Vec<&'static str>>>,
+        entry: &'static str,
+    ) -> impl FnOnce() -> BoxFuture<'static, ()> {
+        let log = log.clone();
+        move || {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                log.lock().unwrap().push(entry);
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn commit_runs_hooks_only() {
+        let log = Arc::default();
+        let mut tx = AsyncTransaction::new(Handle::current());
+        tx.defer_rollback(logger(&log, "undo"));
+        tx.on_commit(logger(&log, "publish"));
+        tx.on_commit(logger(&log, "notify"));
+        assert_eq!(1, tx.len());
+        tx.commit().await;
+        assert_eq!(vec!["publish", "notify"], *log.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn drop_spawns_rollbacks() {
+        let log = Arc::default();
+        let (tx_done, done) = tokio::sync::oneshot::channel();
+        {
+            let mut tx = AsyncTransaction::new(Handle::current());
+            tx.defer_rollback(move || async move { tx_done.send(()).unwrap() });
+            tx.defer_rollback(logger(&log, "second"));
+            tx.on_commit(logger(&log, "never"));
+        }
+        done.await.unwrap();
+        assert_eq!(vec!["second"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn drop_blocks_with_fallback_block() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let log = Arc::default();
+        {
+            let mut tx = AsyncTransaction::new(rt.handle().clone()).fallback_block();
+            tx.defer_rollback(logger(&log, "first"));
+            tx.defer_rollback(logger(&log, "second"));
+        }
+        assert_eq!(vec!["second", "first"], *log.lock().unwrap());
+    }
+}
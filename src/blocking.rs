@@ -0,0 +1,90 @@
+//! Catch blocking callbacks that run on an async runtime.
+
+use crate::report::{report, Hook};
+use crate::{DropGuard, Strategy};
+use std::panic::Location;
+
+/// Receives the creation site of a blocking guard that fired inside a Tokio runtime.
+pub type BlockingHandler = fn(&'static Location<'static>);
+
+static HANDLER: Hook<BlockingHandler> = Hook::new();
+
+/// Routes reports about blocking callbacks on a runtime thread to `handler`.
+///
+/// By default they are printed to stderr, install a handler that panics to make them fatal.
+pub fn set_blocking_handler(handler: BlockingHandler) {
+    HANDLER.set(handler)
+}
+
+fn report_blocking(location: &'static Location<'static>) {
+    match HANDLER.get() {
+        Some(handler) => handler(location),
+        None => report(format_args!(
+            "the blocking callback of the DropGuard created at {} runs inside a Tokio runtime and may stall it",
+            location
+        )),
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> DropGuard<T, F, S> {
+    /// Marks the callback as blocking, e.g. because it joins a thread or does file I/O.
+    ///
+    /// In debug builds the [`BlockingHandler`](type.BlockingHandler.html) is told if the
+    /// callback fires on a thread inside a Tokio runtime, release builds skip the check.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    /// rt.block_on(async {
+    ///     // reports to stderr that the join may stall the runtime
+    ///     let _worker = guard(std::thread::spawn(|| {}), |h| h.join().unwrap()).blocking();
+    /// });
+    /// ```
+    #[must_use]
+    pub fn blocking(self) -> DropGuard<T, impl FnOnce(T), S> {
+        let location = self.created_at();
+        self.rebuild(|data, func| {
+            (data, move |data| {
+                if cfg!(debug_assertions) && tokio::runtime::Handle::try_current().is_ok() {
+                    report_blocking(location);
+                }
+                func(data)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard;
+    use std::sync::Mutex;
+
+    static REPORTED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    fn record(location: &'static Location<'static>) {
+        REPORTED.lock().unwrap().push(location.line());
+    }
+
+    #[test]
+    fn reports_only_inside_a_runtime() {
+        set_blocking_handler(record);
+        let outside = guard((), |_| {}).blocking();
+        let inside = guard((), |_| {}).blocking();
+        let line = line!() - 1;
+        drop(outside);
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async move { drop(inside) });
+
+        let reported = REPORTED.lock().unwrap().clone();
+        if cfg!(debug_assertions) {
+            assert_eq!(vec![line], reported);
+        } else {
+            assert!(reported.is_empty());
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Cancel a `CancellationToken` when the scope ends.
+
+use crate::{guard, guard_on_unwind, DropGuard, OnUnwind};
+use tokio_util::sync::CancellationToken;
+
+/// Cancels the token when the guard is dropped, pass a `child_token()` to only stop a subtree.
+///
+/// Cancel the guard to keep the token alive.
+///
+/// ```
+/// use drop_guard::cancel_guard;
+/// use tokio_util::sync::CancellationToken;
+///
+/// let shutdown = CancellationToken::new();
+/// {
+///     let _workers = cancel_guard(shutdown.child_token());
+///     // ... spawn workers that select on the child token
+/// }
+/// assert!(!shutdown.is_cancelled());
+///
+/// let request = shutdown.child_token();
+/// drop(cancel_guard(shutdown.clone()));
+/// assert!(request.is_cancelled());
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn cancel_guard(
+    token: CancellationToken,
+) -> DropGuard<CancellationToken, fn(CancellationToken)> {
+    guard(token, |t| t.cancel())
+}
+
+/// Cancels the token only if the scope is left by a panic.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn cancel_guard_on_unwind(
+    token: CancellationToken,
+) -> DropGuard<CancellationToken, fn(CancellationToken), OnUnwind> {
+    guard_on_unwind(token, |t| t.cancel())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::catch_unwind;
+
+    #[test]
+    fn cancelled_guard_keeps_token() {
+        let token = CancellationToken::new();
+        cancel_guard(token.clone()).cancel();
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn only_on_unwind() {
+        let token = CancellationToken::new();
+        drop(cancel_guard_on_unwind(token.clone()));
+        assert!(!token.is_cancelled());
+
+        let t = token.clone();
+        let r = catch_unwind(move || {
+            let _g = cancel_guard_on_unwind(t);
+            panic!("worker failed");
+        });
+        assert!(r.is_err());
+        assert!(token.is_cancelled());
+    }
+}
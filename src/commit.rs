@@ -0,0 +1,83 @@
+//! A shared flag that cancels many guards at once.
+
+use crate::{guard, DropGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Gates the callbacks of all guards built from it, once committed they are skipped.
+///
+/// Clones share the flag, so it can be handed to other threads and committed there.
+///
+/// ```
+/// use drop_guard::CommitFlag;
+/// use std::cell::RefCell;
+///
+/// let undone = RefCell::new(Vec::new());
+/// let flag = CommitFlag::new();
+/// {
+///     let _a = flag.guard("create user", |step| undone.borrow_mut().push(step));
+///     let _b = flag.guard("send mail", |step| undone.borrow_mut().push(step));
+///     flag.commit();
+/// }
+/// assert!(undone.borrow().is_empty());
+///
+/// let flag = CommitFlag::new();
+/// {
+///     let _a = flag.guard("create user", |step| undone.borrow_mut().push(step));
+///     let _b = flag.guard("send mail", |step| undone.borrow_mut().push(step));
+/// }
+/// assert_eq!(vec!["send mail", "create user"], *undone.borrow());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CommitFlag {
+    committed: Arc<AtomicBool>,
+}
+
+impl CommitFlag {
+    /// Creates a flag that is not committed yet.
+    pub fn new() -> CommitFlag {
+        CommitFlag::default()
+    }
+
+    /// Commits, the callbacks of all guards built from this flag or its clones are skipped from now on.
+    pub fn commit(&self) {
+        self.committed.store(true, Ordering::Release)
+    }
+
+    /// Whether this flag or one of its clones was committed.
+    pub fn is_committed(&self) -> bool {
+        self.committed.load(Ordering::Acquire)
+    }
+
+    /// Guards the value, the callback only runs if the flag was not committed by then.
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn guard<T, F: FnOnce(T)>(&self, data: T, func: F) -> DropGuard<T, impl FnOnce(T)> {
+        let flag = self.clone();
+        guard(data, move |data| {
+            if !flag.is_committed() {
+                func(data)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn commit_from_other_thread() {
+        let flag = CommitFlag::new();
+        let mut ran = false;
+        {
+            let _g = flag.guard(1, |_| ran = true);
+            let remote = flag.clone();
+            thread::spawn(move || remote.commit()).join().unwrap();
+        }
+        assert!(!ran);
+        assert!(flag.is_committed());
+    }
+}
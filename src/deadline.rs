@@ -0,0 +1,111 @@
+//! Give up on callbacks that take too long.
+
+use crate::report::{report as report_message, Hook};
+use crate::{workers, DropGuard, Strategy};
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// Receives the budget of a callback that exceeded it, together with the creation site of its guard.
+///
+/// Rollbacks of a [`Transaction::with_deadline`](struct.Transaction.html#method.with_deadline), async cleanups
+/// with [`AsyncDropGuard::with_timeout`](struct.AsyncDropGuard.html#method.with_timeout)
+/// and threads with [`JoinGuard::with_join_timeout`](struct.JoinGuard.html#method.with_join_timeout) report here as well.
+pub type DeadlineHandler = fn(Duration, &'static Location<'static>);
+
+static HANDLER: Hook<DeadlineHandler> = Hook::new();
+
+/// Routes reports about callbacks that exceeded their budget to `handler`.
+///
+/// By default they are printed to stderr.
+pub fn set_deadline_handler(handler: DeadlineHandler) {
+    HANDLER.set(handler)
+}
+
+pub(crate) fn report(budget: Duration, location: &'static Location<'static>) {
+    match HANDLER.get() {
+        Some(handler) => handler(budget, location),
+        None => report_message(format_args!(
+            "the callback of the DropGuard created at {} exceeded its budget of {:?} and was given up",
+            location, budget
+        )),
+    }
+}
+
+/// Runs `f` on a worker thread and stops waiting for it after `budget`.
+fn run_within<F: FnOnce() + Send + 'static>(
+    budget: Duration,
+    location: &'static Location<'static>,
+    f: F,
+) {
+    let (done, finished) = mpsc::channel();
+    workers::execute(move || {
+        let _ = done.send(panic::catch_unwind(AssertUnwindSafe(f)));
+    });
+    match finished.recv_timeout(budget) {
+        Ok(Ok(())) => {}
+        Ok(Err(payload)) => panic::resume_unwind(payload),
+        Err(RecvTimeoutError::Timeout) => report(budget, location),
+        Err(RecvTimeoutError::Disconnected) => unreachable!("the worker always sends the outcome"),
+    }
+}
+
+impl<T, F, S> DropGuard<T, F, S>
+where
+    T: Send + 'static,
+    F: FnOnce(T) + Send + 'static,
+    S: Strategy,
+{
+    /// Stops waiting for the callback after `budget`, so a hung cleanup cannot block the dropping thread.
+    ///
+    /// The callback runs on one of a few worker threads that are shared by all guards.
+    /// If it takes longer than `budget`, it is left
+    /// running detached and the [`DeadlineHandler`](type.DeadlineHandler.html) is told.
+    /// A panic of the callback is passed on to the dropping thread.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let start = Instant::now();
+    /// {
+    ///     let _g = guard(17, |_| std::thread::sleep(Duration::from_secs(5)))
+    ///         .with_deadline(Duration::from_millis(10));
+    /// }
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn with_deadline(self, budget: Duration) -> DropGuard<T, impl FnOnce(T), S> {
+        let location = self.created_at();
+        self.rebuild(move |data, func| {
+            (data, move |data| {
+                run_within(budget, location, move || func(data))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::guard;
+    use std::panic::catch_unwind;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn fast_callback_finishes() {
+        let (tx, rx) = mpsc::channel();
+        {
+            let _g = guard(3, move |v| tx.send(v).unwrap()).with_deadline(Duration::from_secs(10));
+        }
+        assert_eq!(Ok(3), rx.try_recv());
+    }
+
+    #[test]
+    fn panic_is_passed_on() {
+        let r = catch_unwind(|| {
+            let _g = guard(3, |_| panic!("cleanup failed")).with_deadline(Duration::from_secs(10));
+        });
+        assert!(r.is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Guards that only exist in debug builds.
+
+#[cfg(debug_assertions)]
+use crate::{guard, DropGuard};
+use std::fmt;
+#[cfg(not(debug_assertions))]
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// A [`DropGuard`](struct.DropGuard.html) in debug builds and a plain wrapper of the value in release builds.
+///
+/// Build it with [`guard_debug_only`](fn.guard_debug_only.html).
+pub struct DebugGuard<T, F: FnOnce(T)> {
+    #[cfg(debug_assertions)]
+    inner: DropGuard<T, F>,
+    #[cfg(not(debug_assertions))]
+    data: T,
+    #[cfg(not(debug_assertions))]
+    func: PhantomData<fn() -> F>,
+}
+
+/// Guards the value like [`guard`](fn.guard.html), but only with `debug_assertions` enabled.
+///
+/// In release builds the callback is dropped right away and the guard is as large as `T`,
+/// handy for checks at drop time that should not cost anything in production.
+///
+/// ```
+/// use drop_guard::guard_debug_only;
+///
+/// let mut request = guard_debug_only(vec!["open"], |steps| {
+///     debug_assert_eq!(Some(&"finish"), steps.last(), "request dropped unfinished");
+/// });
+/// request.push("finish");
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_debug_only<T, F: FnOnce(T)>(data: T, func: F) -> DebugGuard<T, F> {
+    #[cfg(debug_assertions)]
+    {
+        DebugGuard {
+            inner: guard(data, func),
+        }
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        drop(func);
+        DebugGuard {
+            data,
+            func: PhantomData,
+        }
+    }
+}
+
+impl<T, F: FnOnce(T)> DebugGuard<T, F> {
+    /// Takes the value out without running the callback.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        #[cfg(debug_assertions)]
+        {
+            self.inner.into_parts().0
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            self.data
+        }
+    }
+}
+
+impl<T, F: FnOnce(T)> Deref for DebugGuard<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        #[cfg(debug_assertions)]
+        {
+            &self.inner
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            &self.data
+        }
+    }
+}
+
+impl<T, F: FnOnce(T)> DerefMut for DebugGuard<T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        #[cfg(debug_assertions)]
+        {
+            &mut self.inner
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            &mut self.data
+        }
+    }
+}
+
+impl<T: fmt::Debug, F: FnOnce(T)> fmt::Debug for DebugGuard<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugGuard").field("data", &**self).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_only_in_debug() {
+        let mut ran = false;
+        {
+            let mut g = guard_debug_only(1, |_| ran = true);
+            *g += 1;
+            assert_eq!(2, *g);
+        }
+        assert_eq!(cfg!(debug_assertions), ran);
+    }
+
+    #[test]
+    fn into_inner_skips_callback() {
+        let mut ran = false;
+        let g = guard_debug_only(3, |_| ran = true);
+        assert_eq!(3, g.into_inner());
+        assert!(!ran);
+    }
+}
@@ -0,0 +1,197 @@
+//! Macros that run a block at the end of the scope.
+
+/// Runs the block when the current scope ends, like `defer` in Go.
+///
+/// The macro binds the guard itself, so it cannot be dropped right away by accident
+/// like `let _ = guard(...)`. Several `defer!`s run in reverse order.
+///
+/// ```
+/// use drop_guard::defer;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     defer! { log.borrow_mut().push("close file"); }
+///     defer! { log.borrow_mut().push("flush buffer"); }
+///     log.borrow_mut().push("write");
+/// }
+/// assert_eq!(vec!["write", "flush buffer", "close file"], *log.borrow());
+/// ```
+#[macro_export]
+macro_rules! defer {
+    ($($body:tt)*) => {
+        let _guard = $crate::guard((), |()| {
+            $($body)*
+        });
+    };
+}
+
+/// Binds a guard to a name, so it lives until the end of the scope.
+///
+/// `let _ = guard(...)` drops the guard right away and runs the callback too early.
+/// This macro always creates the binding itself, so that mistake cannot be written.
+/// Without a name the guard has no value and is only reachable by the macro.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     guard!(conn = String::from("db") => |c| log.borrow_mut().push(format!("close {}", c)));
+///     guard!(|| log.borrow_mut().push("done".to_string()));
+///     conn.push_str("-primary");
+///     log.borrow_mut().push(format!("use {}", *conn));
+/// }
+/// assert_eq!(vec!["use db-primary", "done", "close db-primary"], *log.borrow());
+/// ```
+#[macro_export]
+macro_rules! guard {
+    ($name:ident = $data:expr => $func:expr) => {
+        #[allow(unused_mut)]
+        let mut $name = $crate::guard($data, $func);
+    };
+    ($func:expr) => {
+        let _guard = $crate::guard((), |()| ($func)());
+    };
+}
+
+/// Runs the `finally` block after the `try` block, also on early returns, `?` and panics.
+///
+/// It evaluates to the value of the `try` block.
+///
+/// ```
+/// use drop_guard::try_finally;
+/// use std::cell::Cell;
+///
+/// let open = Cell::new(true);
+/// let parse = |input: &str| -> Result<u32, std::num::ParseIntError> {
+///     let n = try_finally! {
+///         try {
+///             input.trim().parse::<u32>()? * 2
+///         } finally {
+///             open.set(false);
+///         }
+///     };
+///     Ok(n)
+/// };
+///
+/// assert_eq!(Ok(84), parse(" 42 "));
+/// assert!(!open.get());
+/// open.set(true);
+/// assert!(parse("x").is_err());
+/// assert!(!open.get());
+/// ```
+#[macro_export]
+macro_rules! try_finally {
+    (try $body:block finally $finally:block) => {{
+        let _finally = $crate::guard((), |()| $finally);
+        $body
+    }};
+}
+
+/// Runs the block when the current scope ends because of a panic.
+///
+/// ```
+/// use drop_guard::defer_on_unwind;
+/// use std::panic::catch_unwind;
+/// use std::sync::Mutex;
+///
+/// let log = Mutex::new(Vec::new());
+/// let _ = catch_unwind(|| {
+///     defer_on_unwind! { log.lock().unwrap().push("rollback"); }
+///     panic!("migration failed");
+/// });
+/// {
+///     defer_on_unwind! { log.lock().unwrap().push("not reached"); }
+/// }
+/// assert_eq!(vec!["rollback"], *log.lock().unwrap());
+/// ```
+#[macro_export]
+macro_rules! defer_on_unwind {
+    ($($body:tt)*) => {
+        let _guard = $crate::guard_on_unwind((), |()| {
+            $($body)*
+        });
+    };
+}
+
+/// Runs the block when the current scope ends normally, but not because of a panic.
+///
+/// ```
+/// use drop_guard::defer_on_success;
+/// use std::panic::catch_unwind;
+/// use std::sync::Mutex;
+///
+/// let log = Mutex::new(Vec::new());
+/// let _ = catch_unwind(|| {
+///     defer_on_success! { log.lock().unwrap().push("not reached"); }
+///     panic!("migration failed");
+/// });
+/// {
+///     defer_on_success! { log.lock().unwrap().push("publish"); }
+/// }
+/// assert_eq!(vec!["publish"], *log.lock().unwrap());
+/// ```
+#[macro_export]
+macro_rules! defer_on_success {
+    ($($body:tt)*) => {
+        let _guard = $crate::guard_on_success((), |()| {
+            $($body)*
+        });
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    #[test]
+    fn runs_at_scope_end() {
+        let count = Cell::new(0);
+        {
+            defer! { count.set(count.get() + 1) }
+            assert_eq!(0, count.get());
+        }
+        assert_eq!(1, count.get());
+    }
+
+    #[test]
+    fn conditional_defers() {
+        let log = std::sync::Mutex::new(Vec::new());
+        let r = std::panic::catch_unwind(|| {
+            defer_on_success! { log.lock().unwrap().push("success") }
+            defer_on_unwind! { log.lock().unwrap().push("unwind") }
+            panic!("failed");
+        });
+        assert!(r.is_err());
+        assert_eq!(vec!["unwind"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn guard_binding() {
+        let count = Cell::new(0);
+        {
+            guard!(value = 2 => |v| count.set(count.get() + v));
+            guard!(|| count.set(count.get() * 10));
+            assert_eq!(2, *value);
+            assert_eq!(0, count.get());
+        }
+        assert_eq!(2, count.get());
+    }
+
+    #[test]
+    fn finally_after_value() {
+        let log = std::cell::RefCell::new(Vec::new());
+        let value = try_finally! {
+            try {
+                log.borrow_mut().push("try");
+                7
+            } finally {
+                log.borrow_mut().push("finally");
+            }
+        };
+        assert_eq!(7, value);
+        assert_eq!(vec!["try", "finally"], *log.borrow());
+    }
+}
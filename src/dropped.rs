@@ -0,0 +1,152 @@
+//! A future that resolves once a guard is done.
+
+use crate::{DropGuard, Strategy};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Default)]
+struct State {
+    done: bool,
+    wakers: Vec<Waker>,
+}
+
+/// Resolves once the guard it belongs to is done, see [`DropGuard::dropped`](struct.DropGuard.html#method.dropped).
+///
+/// Clones resolve together, so several tasks can wait for the same guard.
+#[derive(Clone)]
+pub struct Dropped {
+    state: Arc<Mutex<State>>,
+}
+
+impl Dropped {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether the guard is done already.
+    pub fn is_done(&self) -> bool {
+        self.lock().done
+    }
+}
+
+impl Future for Dropped {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock();
+        if state.done {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl std::fmt::Debug for Dropped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dropped")
+            .field("done", &self.is_done())
+            .finish()
+    }
+}
+
+/// Wakes the waiting tasks when the callback finished or was dropped without running.
+struct Notify(Dropped);
+
+impl Drop for Notify {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut state = self.0.lock();
+            state.done = true;
+            std::mem::take(&mut state.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> DropGuard<T, F, S> {
+    /// Hands out a future that resolves once the callback has completed.
+    ///
+    /// It also resolves if the callback never runs, e.g. because the guard was cancelled.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::future::Future;
+    /// use std::task::{Context, Poll, Waker};
+    ///
+    /// let (lease, mut released) = guard("lease-3", |l| println!("released {}", l)).dropped();
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Poll::Pending, std::pin::Pin::new(&mut released).poll(&mut cx));
+    ///
+    /// drop(lease);
+    /// assert_eq!(Poll::Ready(()), std::pin::Pin::new(&mut released).poll(&mut cx));
+    /// ```
+    #[must_use]
+    pub fn dropped(self) -> (DropGuard<T, impl FnOnce(T), S>, Dropped) {
+        let dropped = Dropped {
+            state: Arc::default(),
+        };
+        let notify = Notify(dropped.clone());
+        let g = self.rebuild(move |data, func| {
+            (data, move |data| {
+                let _notify = notify;
+                func(data)
+            })
+        });
+        (g, dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::guard;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn resolves_after_callback() {
+        let cleaned = Arc::new(AtomicBool::new(false));
+        let (g, dropped) = {
+            let cleaned = cleaned.clone();
+            guard((), move |_| cleaned.store(true, Ordering::SeqCst)).dropped()
+        };
+        thread::spawn(move || drop(g));
+        block_on(dropped.clone());
+        assert!(cleaned.load(Ordering::SeqCst));
+        assert!(dropped.is_done());
+    }
+
+    #[test]
+    fn resolves_on_cancel() {
+        let (g, dropped) = guard((), |_| {}).dropped();
+        g.cancel();
+        assert!(dropped.is_done());
+    }
+
+    /// Polls `fut` on this thread, parking until it is woken.
+    fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Unpark(thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark()
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = std::pin::pin!(fut);
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+            thread::park();
+        }
+    }
+}
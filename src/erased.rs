@@ -0,0 +1,64 @@
+//! A type-erased guard to store guards over different values together.
+
+use crate::{DropGuard, Strategy};
+use std::fmt;
+
+/// A guard that only knows how to run its cleanup, created by [`DropGuard::erase`](struct.DropGuard.html#method.erase).
+///
+/// ```
+/// use drop_guard::{guard, ErasedGuard};
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     let mut batch: Vec<ErasedGuard> = Vec::new();
+///     batch.push(guard(1u8, |i| log.borrow_mut().push(i.to_string())).erase());
+///     batch.push(guard("two", |s| log.borrow_mut().push(s.to_string())).erase());
+///     batch.reverse();
+/// }
+/// assert_eq!(vec!["two", "1"], *log.borrow());
+/// ```
+pub struct ErasedGuard<'a> {
+    release: Option<Box<dyn FnOnce() + 'a>>,
+}
+
+impl<'a, T: 'a, F: FnOnce(T) + 'a, S: Strategy + 'a> DropGuard<T, F, S> {
+    /// Erases the type of the value and the callback.
+    #[must_use]
+    #[inline]
+    pub fn erase(self) -> ErasedGuard<'a> {
+        ErasedGuard {
+            release: Some(Box::new(move || drop(self))),
+        }
+    }
+}
+
+impl fmt::Debug for ErasedGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErasedGuard").finish()
+    }
+}
+
+impl Drop for ErasedGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::guard;
+
+    #[test]
+    fn keeps_disarmed_state() {
+        let mut called = false;
+        {
+            let mut g = guard(0, |_| called = true);
+            g.disarm();
+            let _erased = g.erase();
+        }
+        assert!(!called);
+    }
+}
@@ -0,0 +1,119 @@
+//! Collect the errors of fallible callbacks.
+
+use crate::{guard, DropGuard};
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Gathers the errors of callbacks that return a `Result`.
+///
+/// Clones share the collected errors, so guards on other threads can report into it.
+///
+/// ```
+/// use drop_guard::ErrorCollector;
+///
+/// let errors = ErrorCollector::new();
+/// {
+///     let _a = errors.guard("lock-a", |_| Ok(()));
+///     let _b = errors.guard("lock-b", |name| Err(format!("failed to release {}", name)));
+/// }
+/// assert_eq!(vec!["failed to release lock-b".to_string()], errors.take());
+/// assert!(errors.is_empty());
+/// ```
+pub struct ErrorCollector<E> {
+    errors: Arc<Mutex<Vec<E>>>,
+}
+
+impl<E> ErrorCollector<E> {
+    /// Creates a collector without errors.
+    pub fn new() -> ErrorCollector<E> {
+        ErrorCollector {
+            errors: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<E>> {
+        self.errors.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds an error.
+    pub fn push(&self, error: E) {
+        self.lock().push(error)
+    }
+
+    /// Takes all errors collected so far, in the order they happened.
+    pub fn take(&self) -> Vec<E> {
+        mem::take(&mut *self.lock())
+    }
+
+    /// The number of errors collected so far.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no errors were collected so far.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Guards the value with a fallible callback, its error ends up in this collector.
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn guard<T, F>(&self, data: T, func: F) -> DropGuard<T, impl FnOnce(T)>
+    where
+        F: FnOnce(T) -> Result<(), E>,
+    {
+        let collector = self.clone();
+        guard(data, move |data| {
+            if let Err(error) = func(data) {
+                collector.push(error)
+            }
+        })
+    }
+}
+
+impl<E> Clone for ErrorCollector<E> {
+    fn clone(&self) -> Self {
+        ErrorCollector {
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+impl<E> Default for ErrorCollector<E> {
+    fn default() -> Self {
+        ErrorCollector::new()
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for ErrorCollector<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorCollector")
+            .field("errors", &*self.lock())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn collects_across_threads() {
+        let errors = ErrorCollector::new();
+        let workers: Vec<_> = (0..3)
+            .map(|i| {
+                let g = errors.guard(i, |i| if i % 2 == 0 { Err(i) } else { Ok(()) });
+                thread::spawn(move || drop(g))
+            })
+            .collect();
+        for w in workers {
+            w.join().unwrap();
+        }
+        let mut collected = errors.take();
+        collected.sort();
+        assert_eq!(vec![0, 2], collected);
+    }
+}
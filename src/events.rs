@@ -0,0 +1,283 @@
+//! Publish guard creations and drops for dashboards and tracing.
+
+use std::ops::Deref;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The features that need bookkeeping around a callback, checked once per drop
+/// so that guards nobody observes just run their callback.
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+/// Someone subscribed to guard events.
+pub(crate) const SUBSCRIBED: u8 = 1;
+/// A [`guard_named`](fn.guard_named.html) guard was created.
+pub(crate) const NAMED: u8 = 1 << 1;
+/// A [`guard_with_reason`](fn.guard_with_reason.html) guard was created.
+pub(crate) const REASONS: u8 = 1 << 2;
+/// The global [`PanicPolicy`](enum.PanicPolicy.html) is not the default.
+pub(crate) const POLICY: u8 = 1 << 3;
+
+static SUBSCRIBERS: Mutex<Vec<(u64, SyncSender<GuardEvent>)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Switches on the bookkeeping for `feature`.
+#[inline]
+pub(crate) fn enable(feature: u8) {
+    // skip the write once set, so guards created in a loop do not contend on it
+    if ACTIVE.load(Ordering::Relaxed) & feature == 0 {
+        ACTIVE.fetch_or(feature, Ordering::Relaxed);
+    }
+}
+
+/// Switches off the bookkeeping for `feature`.
+pub(crate) fn disable(feature: u8) {
+    ACTIVE.fetch_and(!feature, Ordering::Relaxed);
+}
+
+/// Whether a dropped guard has to do any bookkeeping around its callback.
+#[inline]
+pub(crate) fn instrumented() -> bool {
+    ACTIVE.load(Ordering::Relaxed) != 0
+}
+
+#[inline]
+fn subscribed() -> bool {
+    ACTIVE.load(Ordering::Relaxed) & SUBSCRIBED != 0
+}
+
+/// What happened to a guard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GuardEventKind {
+    /// The guard was created.
+    Created,
+    /// The callback of the guard ran.
+    Dropped,
+}
+
+/// A guard was created or ran its callback, see [`subscribe_guard_events`](fn.subscribe_guard_events.html).
+#[derive(Clone, Debug)]
+pub struct GuardEvent {
+    kind: GuardEventKind,
+    label: Option<&'static str>,
+    type_name: &'static str,
+    location: &'static Location<'static>,
+    timestamp: Option<SystemTime>,
+    duration: Option<Duration>,
+}
+
+impl GuardEvent {
+    /// What happened.
+    pub fn kind(&self) -> GuardEventKind {
+        self.kind
+    }
+
+    /// The name of a [`guard_named`](fn.guard_named.html) guard.
+    pub fn label(&self) -> Option<&'static str> {
+        self.label
+    }
+
+    /// The type of the guarded value.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Where the guard was created.
+    pub fn created_at(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// When it happened, `None` on wasm32 where std has no clock.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    /// How long the callback took, only set for [`GuardEventKind::Dropped`](enum.GuardEventKind.html#variant.Dropped)
+    /// and never on wasm32.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+/// The events of a [`subscribe_guard_events`](fn.subscribe_guard_events.html) subscription.
+///
+/// Derefs to the [`Receiver`], dropping it unsubscribes.
+#[derive(Debug)]
+pub struct GuardEvents {
+    id: u64,
+    receiver: Receiver<GuardEvent>,
+}
+
+impl Deref for GuardEvents {
+    type Target = Receiver<GuardEvent>;
+
+    fn deref(&self) -> &Receiver<GuardEvent> {
+        &self.receiver
+    }
+}
+
+impl Drop for GuardEvents {
+    fn drop(&mut self) {
+        let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|(id, _)| *id != self.id);
+        if subscribers.is_empty() {
+            disable(SUBSCRIBED);
+        }
+    }
+}
+
+/// Receives an event for every guard created or dropped from now on, on any thread.
+///
+/// At most `capacity` events wait in the subscription, further events are
+/// discarded until it is drained, so a slow subscriber never holds up a guard.
+/// A callback that panics is only reported if the
+/// [`PanicPolicy`](enum.PanicPolicy.html) keeps the panic from unwinding.
+///
+/// ```
+/// use drop_guard::{guard_named, subscribe_guard_events, GuardEventKind};
+///
+/// let events = subscribe_guard_events(64);
+/// drop(guard_named("release-lease", 7u32, |_| {}));
+///
+/// let dropped = events
+///     .try_iter()
+///     .find(|e| e.kind() == GuardEventKind::Dropped && e.label() == Some("release-lease"))
+///     .unwrap();
+/// assert_eq!("u32", dropped.type_name());
+/// println!("released in {:?}", dropped.duration().unwrap());
+/// ```
+pub fn subscribe_guard_events(capacity: usize) -> GuardEvents {
+    let (tx, receiver) = mpsc::sync_channel(capacity);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+    subscribers.push((id, tx));
+    enable(SUBSCRIBED);
+    GuardEvents { id, receiver }
+}
+
+fn publish(event: GuardEvent) {
+    let subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+    for (_, tx) in subscribers.iter() {
+        // a full subscription misses the event, a gone one is removed when its `GuardEvents` drops
+        let _ = tx.try_send(event.clone());
+    }
+}
+
+/// Reports a new guard of a `T`.
+#[inline]
+pub(crate) fn created<T>(label: Option<&'static str>, location: &'static Location<'static>) {
+    if subscribed() {
+        publish(GuardEvent {
+            kind: GuardEventKind::Created,
+            label,
+            type_name: std::any::type_name::<T>(),
+            location,
+            timestamp: system_time(),
+            duration: None,
+        });
+    }
+}
+
+/// Starts the clock for a callback if anyone listens.
+#[inline]
+pub(crate) fn start() -> Option<Instant> {
+    if subscribed() {
+        instant()
+    } else {
+        None
+    }
+}
+
+/// Reports the callback of a guard of a `T` that ran since `started`.
+#[inline]
+pub(crate) fn dropped<T>(
+    started: Option<Instant>,
+    label: Option<&'static str>,
+    location: &'static Location<'static>,
+) {
+    if subscribed() {
+        publish(GuardEvent {
+            kind: GuardEventKind::Dropped,
+            label,
+            type_name: std::any::type_name::<T>(),
+            location,
+            timestamp: system_time(),
+            duration: started.map(|started| started.elapsed()),
+        });
+    }
+}
+
+// `SystemTime::now` and `Instant::now` panic on wasm32-unknown-unknown
+
+#[cfg(not(target_arch = "wasm32"))]
+fn system_time() -> Option<SystemTime> {
+    Some(SystemTime::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn system_time() -> Option<SystemTime> {
+    None
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn instant() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn instant() -> Option<Instant> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard;
+
+    #[test]
+    fn created_then_dropped() {
+        let events = subscribe_guard_events(1024);
+        let g = guard(String::from("tmp"), |_| {});
+        let line = line!() - 1;
+        drop(g);
+
+        let mine: Vec<_> = events
+            .try_iter()
+            .filter(|e| e.created_at().file() == file!() && e.created_at().line() == line)
+            .collect();
+        assert_eq!(2, mine.len());
+        assert_eq!(GuardEventKind::Created, mine[0].kind());
+        assert_eq!(None, mine[0].duration());
+        assert_eq!(GuardEventKind::Dropped, mine[1].kind());
+        assert!(mine[1].duration().is_some());
+        assert!(mine[1].timestamp().is_some());
+        assert_eq!("alloc::string::String", mine[1].type_name());
+        assert_eq!(None, mine[1].label());
+    }
+
+    #[test]
+    fn labels_of_named_guards() {
+        let events = subscribe_guard_events(1024);
+        drop(crate::guard_named("flush-metrics", (), |_| {}));
+        let labelled: Vec<_> = events
+            .try_iter()
+            .filter(|e| e.label() == Some("flush-metrics"))
+            .map(|e| e.kind())
+            .collect();
+        assert_eq!(
+            vec![GuardEventKind::Created, GuardEventKind::Dropped],
+            labelled
+        );
+    }
+
+    #[test]
+    fn full_subscription_drops_events() {
+        let events = subscribe_guard_events(1);
+        for i in 0..4 {
+            drop(guard(i, |_| {}));
+        }
+        assert_eq!(1, events.try_iter().count());
+    }
+}
@@ -0,0 +1,203 @@
+//! Extension traits to create guards in method chains.
+
+use crate::{guard, DropGuard};
+use std::fmt;
+use std::panic::Location;
+
+/// Guards any value with a method call, e.g. at the end of a builder chain.
+///
+/// ```
+/// use drop_guard::OnDropExt;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     let _conn = String::from("db")
+///         .to_uppercase()
+///         .on_drop(|c| log.borrow_mut().push(format!("close {}", c)));
+/// }
+/// assert_eq!(vec!["close DB"], *log.borrow());
+/// ```
+pub trait OnDropExt: Sized {
+    /// Guards `self` like [`guard`](fn.guard.html).
+    #[must_use]
+    fn on_drop<F: FnOnce(Self)>(self, func: F) -> DropGuard<Self, F>;
+}
+
+impl<T> OnDropExt for T {
+    #[inline]
+    #[track_caller]
+    fn on_drop<F: FnOnce(T)>(self, func: F) -> DropGuard<T, F> {
+        guard(self, func)
+    }
+}
+
+/// Guards the `Ok` value of a `Result`.
+///
+/// ```
+/// use drop_guard::ResultGuardExt;
+///
+/// let port = "8080"
+///     .parse::<u16>()
+///     .guard_ok(|port| println!("release port {}", port))?;
+/// assert_eq!(8080, *port);
+/// # Ok::<(), std::num::ParseIntError>(())
+/// ```
+pub trait ResultGuardExt<T, E> {
+    /// Guards the value if it is `Ok`, an `Err` is passed on untouched.
+    fn guard_ok<F: FnOnce(T)>(self, func: F) -> Result<DropGuard<T, F>, E>;
+}
+
+impl<T, E> ResultGuardExt<T, E> for Result<T, E> {
+    #[inline]
+    #[track_caller]
+    fn guard_ok<F: FnOnce(T)>(self, func: F) -> Result<DropGuard<T, F>, E> {
+        match self {
+            Ok(data) => Ok(guard(data, func)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Guards the `Some` value of an `Option`.
+///
+/// ```
+/// use drop_guard::OptionGuardExt;
+///
+/// let mut free = vec![3, 7];
+/// let slot = free.pop().guard_some(|slot| println!("return slot {}", slot));
+/// assert_eq!(Some(7), slot.as_deref().copied());
+/// ```
+pub trait OptionGuardExt<T> {
+    /// Guards the value if it is `Some`.
+    fn guard_some<F: FnOnce(T)>(self, func: F) -> Option<DropGuard<T, F>>;
+}
+
+impl<T> OptionGuardExt<T> for Option<T> {
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::manual_map)] // a closure would hide the caller location
+    fn guard_some<F: FnOnce(T)>(self, func: F) -> Option<DropGuard<T, F>> {
+        match self {
+            Some(data) => Some(guard(data, func)),
+            None => None,
+        }
+    }
+}
+
+/// Wraps every item of an iterator in a guard, see [`GuardEachExt::guard_each`](trait.GuardEachExt.html#tymethod.guard_each).
+pub struct GuardEach<I, F> {
+    iter: I,
+    func: F,
+    location: &'static Location<'static>,
+}
+
+/// Guards the items of an iterator one by one.
+///
+/// ```
+/// use drop_guard::GuardEachExt;
+/// use std::sync::mpsc;
+///
+/// let (requeue, requeued) = mpsc::channel();
+/// let mut jobs = vec!["a", "b", "c"].into_iter().guard_each(move |job| requeue.send(job).unwrap());
+///
+/// let first = jobs.next().unwrap();
+/// first.cancel(); // acked
+/// let _second = jobs.next().unwrap();
+/// drop(jobs);
+/// drop(_second); // dropped without an ack, goes back to the queue
+///
+/// assert_eq!(vec!["b"], requeued.iter().collect::<Vec<_>>());
+/// ```
+pub trait GuardEachExt: Iterator + Sized {
+    /// Yields every item in a guard with a clone of `func` as callback.
+    #[must_use]
+    fn guard_each<F: FnOnce(Self::Item) + Clone>(self, func: F) -> GuardEach<Self, F>;
+}
+
+impl<I: Iterator> GuardEachExt for I {
+    #[inline]
+    #[track_caller]
+    fn guard_each<F: FnOnce(I::Item) + Clone>(self, func: F) -> GuardEach<I, F> {
+        GuardEach {
+            iter: self,
+            func,
+            location: Location::caller(),
+        }
+    }
+}
+
+impl<I: Iterator, F: FnOnce(I::Item) + Clone> Iterator for GuardEach<I, F> {
+    type Item = DropGuard<I::Item, F>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        Some(DropGuard::assemble(
+            item,
+            self.func.clone(),
+            true,
+            self.location,
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<I: DoubleEndedIterator, F: FnOnce(I::Item) + Clone> DoubleEndedIterator for GuardEach<I, F> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        Some(DropGuard::assemble(
+            item,
+            self.func.clone(),
+            true,
+            self.location,
+        ))
+    }
+}
+
+impl<I: fmt::Debug, F> fmt::Debug for GuardEach<I, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuardEach")
+            .field("iter", &self.iter)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_creation_site() {
+        let g = 5.on_drop(|_| {});
+        assert_eq!(line!() - 1, g.created_at().line());
+        assert_eq!(file!(), g.created_at().file());
+    }
+
+    #[test]
+    fn err_and_none_skip_callback() {
+        let mut ran = false;
+        let r: Result<u8, ()> = Err(());
+        assert!(r.guard_ok(|_| ran = true).is_err());
+        assert!(None::<u8>.guard_some(|_| ran = true).is_none());
+        assert!(!ran);
+
+        drop(Ok::<_, ()>(1).guard_ok(|_| ran = true));
+        assert!(ran);
+    }
+
+    #[test]
+    fn items_not_taken_are_not_guarded() {
+        let released = std::cell::RefCell::new(Vec::new());
+        {
+            let mut items = (1..=4).guard_each(|i| released.borrow_mut().push(i));
+            let last = items.next_back().unwrap();
+            assert_eq!(4, *last);
+            let _first = items.next();
+            assert_eq!((2, Some(2)), items.size_hint());
+        }
+        assert_eq!(vec![1, 4], *released.borrow());
+    }
+}
@@ -0,0 +1,84 @@
+//! Guards that run by priority instead of declaration order.
+
+use crate::{DropGuard, ErasedGuard, Strategy};
+use std::fmt;
+
+/// Holds guards over different values and runs them by priority on drop.
+///
+/// Higher priorities run first, guards with the same priority run in reverse order of insertion.
+///
+/// ```
+/// use drop_guard::{guard, GuardGroup};
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     let mut group = GuardGroup::new();
+///     group.add(0, guard("database", |s| log.borrow_mut().push(s)));
+///     group.add(10, guard("connection pool", |s| log.borrow_mut().push(s)));
+///     group.add(5, guard("cache", |s| log.borrow_mut().push(s)));
+/// }
+/// assert_eq!(vec!["connection pool", "cache", "database"], *log.borrow());
+/// ```
+#[derive(Default)]
+pub struct GuardGroup<'a> {
+    members: Vec<(i32, ErasedGuard<'a>)>,
+}
+
+impl<'a> GuardGroup<'a> {
+    /// Creates an empty group.
+    pub fn new() -> GuardGroup<'a> {
+        GuardGroup::default()
+    }
+
+    /// Adds a guard that runs before all members with a lower priority.
+    pub fn add<T: 'a, F: FnOnce(T) + 'a, S: Strategy + 'a>(
+        &mut self,
+        priority: i32,
+        guard: DropGuard<T, F, S>,
+    ) {
+        // keep the members sorted by the order they run in
+        let at = self.members.partition_point(|(p, _)| *p > priority);
+        self.members.insert(at, (priority, guard.erase()));
+    }
+
+    /// The number of guards in the group.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Whether the group holds no guards.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+impl fmt::Debug for GuardGroup<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let priorities: Vec<_> = self.members.iter().map(|(p, _)| *p).collect();
+        f.debug_struct("GuardGroup")
+            .field("priorities", &priorities)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard;
+    use std::cell::RefCell;
+
+    #[test]
+    fn same_priority_is_lifo() {
+        let log = RefCell::new(Vec::new());
+        {
+            let mut group = GuardGroup::new();
+            group.add(1, guard(1, |i| log.borrow_mut().push(i)));
+            group.add(1, guard(2, |i| log.borrow_mut().push(i)));
+            group.add(-1, guard(3, |i| log.borrow_mut().push(i)));
+            group.add(1, guard(4, |i| log.borrow_mut().push(i)));
+            assert_eq!(4, group.len());
+        }
+        assert_eq!(vec![4, 2, 1, 3], *log.borrow());
+    }
+}
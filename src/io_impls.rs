@@ -0,0 +1,299 @@
+//! Optional async I/O support, enabled with the `futures` or `tokio` feature.
+//!
+//! Like for futures the I/O object has to be `Unpin` inside a [`DropGuard`], box it or use
+//! a [`PinDropGuard`] otherwise.
+
+use crate::{DropGuard, PinDropGuard, Strategy};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[cfg(feature = "futures")]
+mod with_futures {
+    use super::*;
+    use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite, IoSlice, IoSliceMut};
+
+    /// Read from a guarded async reader.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use futures_util::io::{AsyncReadExt, Cursor};
+    /// use futures_util::FutureExt;
+    ///
+    /// let mut input = guard(Cursor::new(b"hello"), |_| println!("closing input"));
+    /// let mut s = String::new();
+    /// input.read_to_string(&mut s).now_or_never().unwrap().unwrap();
+    /// assert_eq!("hello", s);
+    /// ```
+    impl<T: AsyncRead + Unpin, F: FnOnce(T), S: Strategy> AsyncRead for DropGuard<T, F, S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.data_pin_mut().poll_read(cx, buf)
+        }
+
+        fn poll_read_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &mut [IoSliceMut<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.data_pin_mut().poll_read_vectored(cx, bufs)
+        }
+    }
+
+    /// Write to a guarded async writer, e.g. to send a goodbye on drop.
+    impl<T: AsyncWrite + Unpin, F: FnOnce(T), S: Strategy> AsyncWrite for DropGuard<T, F, S> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.data_pin_mut().poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.data_pin_mut().poll_write_vectored(cx, bufs)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.data_pin_mut().poll_flush(cx)
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.data_pin_mut().poll_close(cx)
+        }
+    }
+
+    impl<T: AsyncBufRead + Unpin, F: FnOnce(T), S: Strategy> AsyncBufRead for DropGuard<T, F, S> {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            self.data_pin_mut().poll_fill_buf(cx)
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            self.data_pin_mut().consume(amt)
+        }
+    }
+
+    impl<T: AsyncRead, F: FnOnce(Pin<&mut T>)> AsyncRead for PinDropGuard<T, F> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.as_pin_mut().poll_read(cx, buf)
+        }
+
+        fn poll_read_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &mut [IoSliceMut<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.as_pin_mut().poll_read_vectored(cx, bufs)
+        }
+    }
+
+    impl<T: AsyncWrite, F: FnOnce(Pin<&mut T>)> AsyncWrite for PinDropGuard<T, F> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.as_pin_mut().poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.as_pin_mut().poll_write_vectored(cx, bufs)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.as_pin_mut().poll_flush(cx)
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.as_pin_mut().poll_close(cx)
+        }
+    }
+
+    impl<T: AsyncBufRead, F: FnOnce(Pin<&mut T>)> AsyncBufRead for PinDropGuard<T, F> {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            self.as_pin_mut().poll_fill_buf(cx)
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            self.as_pin_mut().consume(amt)
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod with_tokio {
+    use super::*;
+    use std::io::IoSlice;
+    use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+    /// Read from a guarded tokio reader.
+    impl<T: AsyncRead + Unpin, F: FnOnce(T), S: Strategy> AsyncRead for DropGuard<T, F, S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.data_pin_mut().poll_read(cx, buf)
+        }
+    }
+
+    /// Write to a guarded tokio writer, e.g. to send a goodbye on drop.
+    impl<T: AsyncWrite + Unpin, F: FnOnce(T), S: Strategy> AsyncWrite for DropGuard<T, F, S> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.data_pin_mut().poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.data_pin_mut().poll_write_vectored(cx, bufs)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            (**self).is_write_vectored()
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.data_pin_mut().poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.data_pin_mut().poll_shutdown(cx)
+        }
+    }
+
+    impl<T: AsyncBufRead + Unpin, F: FnOnce(T), S: Strategy> AsyncBufRead for DropGuard<T, F, S> {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            self.data_pin_mut().poll_fill_buf(cx)
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            self.data_pin_mut().consume(amt)
+        }
+    }
+
+    impl<T: AsyncRead, F: FnOnce(Pin<&mut T>)> AsyncRead for PinDropGuard<T, F> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.as_pin_mut().poll_read(cx, buf)
+        }
+    }
+
+    impl<T: AsyncWrite, F: FnOnce(Pin<&mut T>)> AsyncWrite for PinDropGuard<T, F> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.as_pin_mut().poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.as_pin_mut().poll_write_vectored(cx, bufs)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            (**self).is_write_vectored()
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.as_pin_mut().poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.as_pin_mut().poll_shutdown(cx)
+        }
+    }
+
+    impl<T: AsyncBufRead, F: FnOnce(Pin<&mut T>)> AsyncBufRead for PinDropGuard<T, F> {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            self.as_pin_mut().poll_fill_buf(cx)
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            self.as_pin_mut().consume(amt)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{guard, pin_guard};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        #[tokio::test]
+        async fn goodbye_on_drop() {
+            let mut sent = Vec::new();
+            {
+                let mut socket = guard(Vec::new(), |mut out: Vec<u8>| {
+                    out.extend_from_slice(b"bye\n");
+                    sent = out;
+                });
+                socket.write_all(b"hello\n").await.unwrap();
+            }
+            assert_eq!(b"hello\nbye\n", &sent[..]);
+        }
+
+        #[tokio::test]
+        async fn pinned_reader_passes_through() {
+            let mut lines = Box::pin(pin_guard(BufReader::new(&b"a\nb\n"[..]), |_| {})).lines();
+            assert_eq!(Some("a".to_string()), lines.next_line().await.unwrap());
+            assert_eq!(Some("b".to_string()), lines.next_line().await.unwrap());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "futures"))]
+mod tests {
+    use crate::guard;
+    use futures_util::io::{AsyncBufReadExt, AsyncWriteExt, Cursor};
+    use futures_util::FutureExt;
+
+    #[test]
+    fn buffered_read_passes_through() {
+        let mut rest = None;
+        {
+            let mut input = guard(Cursor::new(b"one\ntwo\n".to_vec()), |c| {
+                rest = Some(c.position())
+            });
+            let mut line = String::new();
+            input.read_line(&mut line).now_or_never().unwrap().unwrap();
+            assert_eq!("one\n", line);
+        }
+        assert_eq!(Some(4), rest);
+    }
+
+    #[test]
+    fn close_passes_through() {
+        let mut out = guard(Vec::<u8>::new(), |buf| assert_eq!(b"done", &buf[..]));
+        out.write_all(b"done").now_or_never().unwrap().unwrap();
+        out.close().now_or_never().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+//! Join threads that run out of scope.
+
+use crate::deadline;
+use crate::report::{report, Hook};
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::panic::{resume_unwind, Location};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Receives the panic of a thread joined by a dropped [`JoinGuard`](struct.JoinGuard.html), together with the creation site of the guard.
+pub type JoinPanicHandler = fn(Box<dyn Any + Send>, &'static Location<'static>);
+
+static HANDLER: Hook<JoinPanicHandler> = Hook::new();
+
+/// Routes panics of threads joined by dropped guards to `handler` instead of resuming them.
+pub fn set_join_panic_handler(handler: JoinPanicHandler) {
+    HANDLER.set(handler)
+}
+
+fn forward(payload: Box<dyn Any + Send>, location: &'static Location<'static>) {
+    match HANDLER.get() {
+        Some(handler) => handler(payload, location),
+        // a second panic while unwinding would abort
+        None if thread::panicking() => report(format_args!(
+            "the thread joined by the JoinGuard created at {} panicked while this thread was panicking too",
+            location
+        )),
+        None => resume_unwind(payload),
+    }
+}
+
+/// Polls the thread with growing pauses, std has no join with a timeout.
+fn finished_within<T>(handle: &JoinHandle<T>, budget: Duration) -> bool {
+    let deadline = Instant::now() + budget;
+    let mut pause = Duration::from_micros(100);
+    while !handle.is_finished() {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        thread::sleep(pause.min(deadline - now));
+        pause = (pause * 2).min(Duration::from_millis(10));
+    }
+    true
+}
+
+/// Joins the thread when it is dropped and passes a panic of the thread on to the dropping thread.
+///
+/// Install a [`JoinPanicHandler`](type.JoinPanicHandler.html) to handle the panics elsewhere.
+/// Use [`join_now`](#method.join_now) for the result of the thread.
+///
+/// ```
+/// use drop_guard::JoinGuard;
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// let (tx, rx) = mpsc::channel();
+/// {
+///     let _worker = JoinGuard::new(thread::spawn(move || tx.send("done").unwrap()));
+/// }
+/// // the worker was joined at the end of the scope
+/// assert_eq!(Ok("done"), rx.try_recv());
+///
+/// let answer = JoinGuard::new(thread::spawn(|| 6 * 7));
+/// assert_eq!(42, answer.join_now().unwrap());
+/// ```
+pub struct JoinGuard<T> {
+    handle: Option<JoinHandle<T>>,
+    timeout: Option<Duration>,
+    location: &'static Location<'static>,
+}
+
+impl<T> JoinGuard<T> {
+    /// Guards the thread behind `handle`.
+    #[must_use]
+    #[track_caller]
+    pub fn new(handle: JoinHandle<T>) -> Self {
+        JoinGuard {
+            handle: Some(handle),
+            timeout: None,
+            location: Location::caller(),
+        }
+    }
+
+    /// Waits at most `budget` for the thread on drop, then leaves it running detached.
+    ///
+    /// Giving up is reported to the [`DeadlineHandler`](type.DeadlineHandler.html),
+    /// so a stuck worker can not hold up a shutdown.
+    ///
+    /// ```
+    /// use drop_guard::guard_spawn;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let start = Instant::now();
+    /// {
+    ///     let _stuck = guard_spawn(|| std::thread::sleep(Duration::from_secs(5)))
+    ///         .with_join_timeout(Duration::from_millis(10));
+    /// }
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn with_join_timeout(mut self, budget: Duration) -> Self {
+        self.timeout = Some(budget);
+        self
+    }
+
+    /// Joins the thread right away and hands back its result, a panic included.
+    pub fn join_now(mut self) -> thread::Result<T> {
+        self.take().join()
+    }
+
+    /// Takes the handle out, the thread is not joined anymore.
+    pub fn into_inner(mut self) -> JoinHandle<T> {
+        self.take()
+    }
+
+    /// Where the guard was created.
+    pub fn created_at(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    fn take(&mut self) -> JoinHandle<T> {
+        self.handle.take().expect("the guard is only emptied once")
+    }
+}
+
+impl JoinGuard<()> {
+    /// Configures a thread before spawning it with a guard, like `std::thread::Builder`.
+    pub fn builder() -> JoinGuardBuilder {
+        JoinGuardBuilder {
+            inner: thread::Builder::new(),
+        }
+    }
+}
+
+/// Spawns a thread and guards it in one call, so it can not be left unjoined by accident.
+///
+/// ```
+/// use drop_guard::guard_spawn;
+///
+/// let mut results = Vec::new();
+/// {
+///     let first = guard_spawn(|| 1 + 1);
+///     results.push(first.join_now().unwrap());
+///     let _background = guard_spawn(|| println!("flushing logs"));
+/// }
+/// assert_eq!(vec![2], results);
+/// ```
+#[must_use]
+#[track_caller]
+pub fn guard_spawn<T, F>(f: F) -> JoinGuard<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    JoinGuard::new(thread::spawn(f))
+}
+
+/// Sets up the thread of a [`JoinGuard`](struct.JoinGuard.html), see [`JoinGuard::builder`](struct.JoinGuard.html#method.builder).
+///
+/// ```
+/// use drop_guard::JoinGuard;
+///
+/// let worker = JoinGuard::builder()
+///     .name("compactor".into())
+///     .stack_size(64 * 1024)
+///     .spawn(|| std::thread::current().name().map(str::to_owned))
+///     .unwrap();
+/// assert_eq!(Some("compactor".to_string()), worker.join_now().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct JoinGuardBuilder {
+    inner: thread::Builder,
+}
+
+impl JoinGuardBuilder {
+    /// Names the thread.
+    #[must_use]
+    pub fn name(mut self, name: String) -> Self {
+        self.inner = self.inner.name(name);
+        self
+    }
+
+    /// Sets the stack size of the thread in bytes.
+    #[must_use]
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.inner = self.inner.stack_size(size);
+        self
+    }
+
+    /// Spawns the thread and guards it, fails like `std::thread::Builder::spawn`.
+    #[track_caller]
+    pub fn spawn<T, F>(self, f: F) -> io::Result<JoinGuard<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let location = Location::caller();
+        let handle = self.inner.spawn(f)?;
+        Ok(JoinGuard {
+            handle: Some(handle),
+            timeout: None,
+            location,
+        })
+    }
+}
+
+impl<T> Deref for JoinGuard<T> {
+    type Target = JoinHandle<T>;
+
+    fn deref(&self) -> &JoinHandle<T> {
+        self.handle
+            .as_ref()
+            .expect("the guard is only emptied once")
+    }
+}
+
+impl<T> fmt::Debug for JoinGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinGuard")
+            .field("thread", self.thread())
+            .field("location", &self.location)
+            .finish()
+    }
+}
+
+impl<T> Drop for JoinGuard<T> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            if let Some(budget) = self.timeout {
+                if !finished_within(&handle, budget) {
+                    deadline::report(budget, self.location);
+                    return;
+                }
+            }
+            if let Err(payload) = handle.join() {
+                forward(payload, self.location);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::catch_unwind;
+
+    #[test]
+    fn resumes_the_panic() {
+        let r = catch_unwind(|| {
+            let _worker = JoinGuard::new(thread::spawn(|| panic!("worker failed")));
+        });
+        assert_eq!(
+            Some(&"worker failed"),
+            r.unwrap_err().downcast_ref::<&str>()
+        );
+    }
+
+    #[test]
+    fn join_now_returns_the_panic() {
+        let worker = JoinGuard::new(thread::spawn(|| -> u8 { panic!("worker failed") }));
+        assert!(worker.join_now().is_err());
+    }
+
+    #[test]
+    fn spawned_guards_remember_the_caller() {
+        let worker = guard_spawn(|| ());
+        assert_eq!(line!() - 1, worker.created_at().line());
+        let worker = JoinGuard::builder().spawn(|| ()).unwrap();
+        assert_eq!(line!() - 1, worker.created_at().line());
+    }
+
+    #[test]
+    fn join_timeout_still_joins_fast_threads() {
+        let r = catch_unwind(|| {
+            let _worker =
+                guard_spawn(|| panic!("worker failed")).with_join_timeout(Duration::from_secs(60));
+        });
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn into_inner_detaches() {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let handle = JoinGuard::new(thread::spawn(move || rx.recv().unwrap_err())).into_inner();
+        drop(tx);
+        handle.join().unwrap();
+    }
+}
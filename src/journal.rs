@@ -0,0 +1,235 @@
+//! Rollbacks written to a file so they survive a crash.
+
+use crate::GuardStack;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Writes a line and makes sure it reached the disk.
+fn append(file: &Mutex<File>, line: &str) -> io::Result<()> {
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    writeln!(file, "{}", line)?;
+    file.sync_data()
+}
+
+/// Records every rollback step in a file before its action runs, see [`replay`](#method.replay).
+///
+/// Dropping the journal without [`commit`](#method.commit) runs the rollbacks in reverse order.
+/// The file is removed once all steps are committed or rolled back.
+/// If the process dies in between or a rollback fails, the file keeps the outstanding steps.
+///
+/// ```
+/// use drop_guard::Journal;
+///
+/// let path = std::env::temp_dir().join(format!("drop_guard_doc_journal_{}", std::process::id()));
+/// # let _ = std::fs::remove_file(&path);
+/// let mut journal = Journal::create(&path)?;
+/// journal.record("remove /etc/app.conf", || println!("removing /etc/app.conf"))?;
+/// // write /etc/app.conf ...
+/// journal.commit()?;
+/// assert!(!path.exists());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Journal<'a> {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    next_id: u64,
+    /// The number of steps whose rollback ran and was written to the file.
+    rolled_back: Arc<AtomicU64>,
+    rollbacks: GuardStack<'a>,
+}
+
+impl<'a> Journal<'a> {
+    /// Creates the journal file, it must not exist yet.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Journal<'a>> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Journal {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            next_id: 0,
+            rolled_back: Arc::new(AtomicU64::new(0)),
+            rollbacks: GuardStack::new(),
+        })
+    }
+
+    /// Writes `description` to the journal and registers `rollback`, call it before the action runs.
+    ///
+    /// The description must fit on one line, [`replay`](#method.replay) hands it back after a crash.
+    pub fn record<F: FnOnce() + 'a>(&mut self, description: &str, rollback: F) -> io::Result<()> {
+        if description.contains(['\n', '\r']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "journal descriptions must fit on one line",
+            ));
+        }
+        let id = self.next_id;
+        append(&self.file, &format!("+{} {}", id, description))?;
+        self.next_id += 1;
+
+        let file = self.file.clone();
+        let rolled_back = self.rolled_back.clone();
+        self.rollbacks.push(move || {
+            rollback();
+            // if this fails the step is rolled back again by the replay
+            if append(&file, &format!("-{}", id)).is_ok() {
+                rolled_back.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        Ok(())
+    }
+
+    /// Keeps all steps and removes the journal file.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.rollbacks.dismiss_all();
+        fs::remove_file(&self.path)
+    }
+
+    /// Rolls back the outstanding steps of a journal left behind by a crash, the latest first.
+    ///
+    /// `rollback` receives the descriptions of the steps, the file is removed afterwards.
+    /// Returns the number of rolled back steps, a missing file means there is nothing to do.
+    ///
+    /// ```
+    /// use drop_guard::Journal;
+    ///
+    /// let path = std::env::temp_dir().join(format!("drop_guard_doc_replay_{}", std::process::id()));
+    /// # let _ = std::fs::remove_file(&path);
+    /// let mut journal = Journal::create(&path)?;
+    /// journal.record("remove user bob", || {})?;
+    /// std::mem::forget(journal); // crash
+    ///
+    /// let mut undone = Vec::new();
+    /// assert_eq!(1, Journal::replay(&path, |step| undone.push(step.to_string()))?);
+    /// assert_eq!(vec!["remove user bob"], undone);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn replay<P: AsRef<Path>, F: FnMut(&str)>(path: P, mut rollback: F) -> io::Result<usize> {
+        let path = path.as_ref();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let mut outstanding = BTreeMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if let Some(done) = line.strip_prefix('-') {
+                if let Ok(id) = done.parse::<u64>() {
+                    outstanding.remove(&id);
+                }
+            } else if let Some(step) = line.strip_prefix('+') {
+                if let Some((id, description)) = step.split_once(' ') {
+                    if let Ok(id) = id.parse::<u64>() {
+                        outstanding.insert(id, description.to_string());
+                    }
+                }
+            }
+        }
+
+        let file = Mutex::new(OpenOptions::new().append(true).open(path)?);
+        let count = outstanding.len();
+        for (id, description) in outstanding.into_iter().rev() {
+            rollback(&description);
+            append(&file, &format!("-{}", id))?;
+        }
+        fs::remove_file(path)?;
+        Ok(count)
+    }
+}
+
+impl fmt::Debug for Journal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal")
+            .field("path", &self.path)
+            .field("steps", &self.rollbacks.len())
+            .finish()
+    }
+}
+
+impl Drop for Journal<'_> {
+    fn drop(&mut self) {
+        drop(mem::take(&mut self.rollbacks));
+        // a rollback that panicked, even if the panic was caught, keeps the file for the replay
+        if self.rolled_back.load(Ordering::Relaxed) == self.next_id {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::env;
+
+    fn temp(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("drop_guard_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn drop_rolls_back() {
+        let path = temp("drop");
+        let log = RefCell::new(Vec::new());
+        {
+            let mut journal = Journal::create(&path).unwrap();
+            journal.record("a", || log.borrow_mut().push("a")).unwrap();
+            journal.record("b", || log.borrow_mut().push("b")).unwrap();
+        }
+        assert_eq!(vec!["b", "a"], *log.borrow());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn replay_skips_finished_steps() {
+        let path = temp("replay");
+        fs::write(&path, "+0 first\n+1 second\n-1\n+2 third\n").unwrap();
+        let mut undone = Vec::new();
+        assert_eq!(
+            2,
+            Journal::replay(&path, |s| undone.push(s.to_string())).unwrap()
+        );
+        assert_eq!(vec!["third", "first"], undone);
+        assert!(!path.exists());
+        assert_eq!(0, Journal::replay(&path, |_| unreachable!()).unwrap());
+    }
+
+    #[test]
+    fn multiline_description_is_rejected() {
+        let path = temp("multiline");
+        let mut journal = Journal::create(&path).unwrap();
+        let err = journal.record("a\nb", || {}).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        journal.commit().unwrap();
+    }
+
+    #[test]
+    fn failed_rollback_keeps_the_file() {
+        let path = temp("failed");
+        let r = std::panic::catch_unwind(|| {
+            let mut journal = Journal::create(&path).unwrap();
+            journal.record("a", || {}).unwrap();
+            journal.record("b", || panic!("rollback failed")).unwrap();
+            panic!("work failed");
+        });
+        assert!(r.is_err());
+
+        let mut undone = Vec::new();
+        assert_eq!(
+            1,
+            Journal::replay(&path, |s| undone.push(s.to_string())).unwrap()
+        );
+        assert_eq!(vec!["b"], undone);
+    }
+}
@@ -24,96 +24,1587 @@
 //! ```
 //!
 
-use std::boxed::Box;
-use std::ops::{Deref, DerefMut, Drop, FnMut};
+use std::borrow::{Borrow, BorrowMut};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut, Drop, FnOnce, Index, IndexMut};
+use std::panic::{AssertUnwindSafe, Location, RefUnwindSafe, UnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+mod stack;
+pub use stack::{GuardStack, Savepoint};
+
+mod strategy;
+pub use strategy::{Always, OnSuccess, OnUnwind, Strategy};
+
+#[cfg(any(feature = "futures", feature = "tokio"))]
+mod abort;
+#[cfg(feature = "tokio")]
+pub use abort::{abort_and_join, abort_guard};
+#[cfg(feature = "futures")]
+pub use abort::{guard_abort_handle, guard_abortable, AbortOnDrop};
+
+mod acquire;
+pub use acquire::acquire_all;
+
+mod async_guard;
+#[cfg(feature = "async-std")]
+pub use async_guard::AsyncStd;
+#[cfg(feature = "smol")]
+pub use async_guard::Smol;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use async_guard::WasmLocal;
+pub use async_guard::{
+    async_guard, AsyncCleanup, AsyncDropGuard, BlockOn, BoxFuture, Spawner, SyncDropStrategy,
+};
+
+mod async_local;
+pub use async_local::{
+    local_async_guard, LocalAsyncCleanup, LocalAsyncDropGuard, LocalBoxFuture, LocalSpawner,
+};
+
+mod async_scope;
+pub use async_scope::{async_scope, AsyncScope};
+
+mod async_transaction;
+pub use async_transaction::AsyncTransaction;
+
+#[cfg(feature = "tokio")]
+mod blocking;
+#[cfg(feature = "tokio")]
+pub use blocking::{set_blocking_handler, BlockingHandler};
+
+#[cfg(feature = "tokio-util")]
+mod cancel;
+#[cfg(feature = "tokio-util")]
+pub use cancel::{cancel_guard, cancel_guard_on_unwind};
+
+mod commit;
+pub use commit::CommitFlag;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod deadline;
+#[cfg(not(target_arch = "wasm32"))]
+pub use deadline::{set_deadline_handler, DeadlineHandler};
+
+mod debug_only;
+pub use debug_only::{guard_debug_only, DebugGuard};
+
+mod defer;
+
+mod dropped;
+pub use dropped::Dropped;
+
+mod erased;
+pub use erased::ErasedGuard;
+
+mod errors;
+pub use errors::ErrorCollector;
+
+mod events;
+pub use events::{subscribe_guard_events, GuardEvent, GuardEventKind, GuardEvents};
+
+mod ext;
+pub use ext::{GuardEach, GuardEachExt, OnDropExt, OptionGuardExt, ResultGuardExt};
+
+mod group;
+pub use group::GuardGroup;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod join;
+#[cfg(not(target_arch = "wasm32"))]
+pub use join::{
+    guard_spawn, set_join_panic_handler, JoinGuard, JoinGuardBuilder, JoinPanicHandler,
+};
+
+mod journal;
+pub use journal::Journal;
+
+mod name;
+pub use name::{current_guard_name, guard_named};
+
+mod panic_details;
+pub use panic_details::{guard_with_panic_info, PanicDetails};
+
+mod pin;
+pub use pin::{pin_guard, PinDropGuard};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod pool;
+#[cfg(not(target_arch = "wasm32"))]
+pub use pool::CleanupPool;
+
+mod reason;
+pub use reason::{guard_with_reason, DropReason};
+
+mod registry;
+pub use registry::{flush_registered, guard_registered, install_abort_hook, RegisteredGuard};
+
+mod report;
+pub use report::{set_report_handler, ReportHandler};
+
+mod result_handle;
+pub use result_handle::{guard_with_result, ResultHandle};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod retry;
+#[cfg(not(target_arch = "wasm32"))]
+pub use retry::{guard_with_retry, Backoff, Retry};
+
+mod rollback;
+pub use rollback::Rollback;
+
+mod saga;
+pub use saga::{Saga, SagaError};
+
+mod shared;
+pub use shared::{shared_guard, SharedGuard};
+
+mod transaction;
+pub use transaction::{transaction, NestedTransaction, Transaction, TransactionScope};
+
+mod two_phase;
+pub use two_phase::TwoPhaseCommit;
+
+mod undo;
+pub use undo::{UndoOnDrop, UndoStack};
+
+mod unwind;
+pub use unwind::{set_panic_policy, set_unwind_panic_handler, PanicPolicy, UnwindPanicHandler};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod timer;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod workers;
+
+/// Turns `let` statements of a function into guards, needs the `macros` feature.
+///
+/// Put `#[cleanup]` on the function and `#[cleanup(callback)]` on every `let` that should
+/// be guarded. The binding becomes a [`DropGuard`](struct.DropGuard.html) with that callback.
+///
+/// ```
+/// use drop_guard::cleanup;
+/// use std::cell::RefCell;
+///
+/// #[cleanup]
+/// fn handle(log: &RefCell<Vec<String>>) -> Result<usize, std::num::ParseIntError> {
+///     #[cleanup(|c: String| log.borrow_mut().push(format!("close {}", c)))]
+///     let conn: String = "db".to_string();
+///     let n = "3".parse::<usize>()?;
+///     Ok(conn.len() + n)
+/// }
+///
+/// let log = RefCell::new(Vec::new());
+/// assert_eq!(Ok(5), handle(&log));
+/// assert_eq!(vec!["close db"], *log.borrow());
+/// ```
+#[cfg(feature = "macros")]
+pub use drop_guard_macros::cleanup;
+/// Implements `Drop` to clean up fields, needs the `macros` feature.
+///
+/// Every field with `#[on_drop(path::to::cleanup)]` is handed to its cleanup as `&mut T`,
+/// in the order the fields are declared. The other cleanups still run if one panics.
+///
+/// ```
+/// use drop_guard::Guarded;
+///
+/// fn close(socket: &mut String) {
+///     println!("closing {}", socket);
+/// }
+///
+/// fn flush(buffer: &mut Vec<u8>) {
+///     buffer.clear();
+/// }
+///
+/// #[derive(Guarded)]
+/// struct Client {
+///     #[on_drop(flush)]
+///     buffer: Vec<u8>,
+///     #[on_drop(close)]
+///     socket: String,
+///     retries: u8,
+/// }
+///
+/// let client = Client { buffer: vec![1, 2], socket: "10.0.0.1:80".into(), retries: 3 };
+/// drop(client);
+/// ```
+#[cfg(feature = "macros")]
+pub use drop_guard_macros::Guarded;
+// the macros name this crate by its path
+#[cfg(all(test, feature = "macros"))]
+extern crate self as drop_guard;
+
+#[cfg(any(feature = "futures", feature = "tokio"))]
+mod io_impls;
+#[cfg(feature = "futures")]
+mod stream_impls;
+
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "serde")]
+pub use serde_impls::GuardSeed;
 
 #[must_use]
 #[inline]
-pub fn guard<T: Sized, F: FnMut(T)>(thing: T, func: F) -> DropGuard<T, F> {
+#[track_caller]
+pub fn guard<T: Sized, F: FnOnce(T)>(thing: T, func: F) -> DropGuard<T, F> {
+    events::created::<T>(None, Location::caller());
     DropGuard {
-        data: Some(thing),
-        func: Box::new(func),
+        data: ManuallyDrop::new(thing),
+        func: ManuallyDrop::new(func),
+        armed: true,
+        policy: None,
+        location: Location::caller(),
+        strategy: PhantomData,
+    }
+}
+
+/// Acquires the value with `init` and guards it with `func` in one expression.
+///
+/// This keeps acquisition and release side by side, like an open/close pair.
+///
+/// ```
+/// use drop_guard::guard_with;
+///
+/// let mut closed = false;
+/// {
+///     let conn = guard_with(|| String::from("connection"), |_| closed = true);
+///     assert_eq!("connection", *conn);
+/// }
+/// assert!(closed);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_with<T, I: FnOnce() -> T, F: FnOnce(T)>(init: I, func: F) -> DropGuard<T, F> {
+    guard(init(), func)
+}
+
+/// Like [`guard_with`](fn.guard_with.html) for fallible acquisition.
+///
+/// The callback only runs if `init` succeeded, the error is returned unchanged.
+///
+/// ```
+/// use drop_guard::try_guard;
+///
+/// let port = try_guard(|| "8080".parse::<u16>(), |p| println!("releasing port {}", p))?;
+/// assert_eq!(8080, *port);
+///
+/// assert!(try_guard(|| "eighty".parse::<u16>(), |_| unreachable!()).is_err());
+/// # Ok::<(), std::num::ParseIntError>(())
+/// ```
+#[inline]
+#[track_caller]
+pub fn try_guard<T, E, I, F>(init: I, func: F) -> Result<DropGuard<T, F>, E>
+where
+    I: FnOnce() -> Result<T, E>,
+    F: FnOnce(T),
+{
+    Ok(guard(init()?, func))
+}
+
+/// Guards the value, but only runs the callback if `condition` is `true`.
+///
+/// Both cases share one type, so e.g. a dry run and a real run need no separate code paths.
+///
+/// ```
+/// use drop_guard::guard_if;
+///
+/// let dry_run = true;
+/// let mut deleted = false;
+/// {
+///     let mut file = guard_if(!dry_run, String::from("out.tmp"), |_| deleted = true);
+///     file.push_str(".bak");
+/// }
+/// assert!(!deleted);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_if<T, F: FnOnce(T)>(
+    condition: bool,
+    data: T,
+    func: F,
+) -> DropGuard<T, impl FnOnce(T)> {
+    guard(data, move |data| {
+        if condition {
+            func(data)
+        }
+    })
+}
+
+/// Guards the value and hands `ctx` to the callback next to it.
+///
+/// The context lives in the guard, e.g. a logger, a metrics handle or a transaction id.
+///
+/// ```
+/// use drop_guard::guard_with_ctx;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     let _row = guard_with_ctx(17, (&log, "tx-42"), |row, (log, tx)| {
+///         log.borrow_mut().push(format!("{}: rollback row {}", tx, row))
+///     });
+/// }
+/// assert_eq!(vec!["tx-42: rollback row 17"], *log.borrow());
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_with_ctx<T, C, F: FnOnce(T, C)>(
+    data: T,
+    ctx: C,
+    func: F,
+) -> DropGuard<T, impl FnOnce(T)> {
+    guard(data, move |data| func(data, ctx))
+}
+
+/// Runs `body` with the value and `cleanup` right after it, also if `body` panics.
+///
+/// Returns the result of `body`, a shortcut for [`guard(data, cleanup).scope(body)`](struct.DropGuard.html#method.scope).
+///
+/// ```
+/// use drop_guard::with_guard;
+///
+/// let mut log = Vec::new();
+/// let total = with_guard(
+///     vec![1, 2, 3],
+///     |v| log.push(format!("released {} items", v.len())),
+///     |v| v.iter().sum::<i32>(),
+/// );
+/// assert_eq!(6, total);
+/// assert_eq!(vec!["released 3 items"], log);
+/// ```
+#[inline]
+#[track_caller]
+pub fn with_guard<T, R, F, B>(data: T, cleanup: F, body: B) -> R
+where
+    F: FnOnce(T),
+    B: FnOnce(&mut T) -> R,
+{
+    guard(data, cleanup).scope(body)
+}
+
+/// A guard with a plain function pointer as callback, so its type can be named.
+///
+/// ```
+/// use drop_guard::{guard_fn, FnGuard};
+///
+/// struct Connection {
+///     socket: FnGuard<String>,
+/// }
+///
+/// fn close(socket: String) {
+///     println!("closing {}", socket);
+/// }
+///
+/// let conn = Connection { socket: guard_fn(String::from("10.0.0.1:80"), close) };
+/// assert_eq!("10.0.0.1:80", *conn.socket);
+/// ```
+pub type FnGuard<T> = DropGuard<T, fn(T)>;
+
+/// Creates a [`FnGuard`](type.FnGuard.html) from your data and a function pointer.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_fn<T>(data: T, func: fn(T)) -> FnGuard<T> {
+    guard(data, func)
+}
+
+/// Guards the value without a callback yet, use [`attach`](struct.DropGuard.html#method.attach) to add one later.
+///
+/// ```
+/// use drop_guard::guard_noop;
+///
+/// let mut log = Vec::new();
+/// {
+///     let resource = guard_noop(String::from("tcp"));
+///     // the value is managed by the guard while we decide about the cleanup
+///     let kind = resource.clone();
+///     let _resource = resource.attach(|r| log.push(format!("closing {} ({})", r, kind)));
+/// }
+/// assert_eq!(vec!["closing tcp (tcp)"], log);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_noop<T>(data: T) -> FnGuard<T> {
+    fn noop<T>(_: T) {}
+    guard(data, noop::<T>)
+}
+
+/// Guards the value, but only runs the callback if the scope is left by a panic.
+///
+/// Use it to roll back changes on failure.
+///
+/// ```
+/// use drop_guard::guard_on_unwind;
+/// use std::panic::catch_unwind;
+///
+/// let result = catch_unwind(|| {
+///     let _rollback = guard_on_unwind("config", |c| println!("restoring {}", c));
+///     panic!("applying the config failed");
+/// });
+/// assert!(result.is_err());
+///
+/// let mut rolled_back = false;
+/// {
+///     let _rollback = guard_on_unwind("config", |_| rolled_back = true);
+/// }
+/// assert!(!rolled_back);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_on_unwind<T, F: FnOnce(T)>(data: T, func: F) -> DropGuard<T, F, OnUnwind> {
+    DropGuard::with_strategy(data, func)
+}
+
+/// Guards the value, but only runs the callback if the scope is left normally.
+///
+/// Use it for commit style actions that must be skipped if a panic tears down the scope.
+///
+/// ```
+/// use drop_guard::guard_on_success;
+///
+/// let mut published = false;
+/// {
+///     let _publish = guard_on_success("report", |_| published = true);
+/// }
+/// assert!(published);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_on_success<T, F: FnOnce(T)>(data: T, func: F) -> DropGuard<T, F, OnSuccess> {
+    DropGuard::with_strategy(data, func)
+}
+
+/// Runs the callback on `&mut T` as a pre-drop hook, the value is dropped normally afterwards.
+///
+/// ```
+/// use drop_guard::guard_inspect;
+/// use std::io::{BufWriter, Write};
+///
+/// let mut out = guard_inspect(BufWriter::new(Vec::new()), |w| {
+///     w.flush().expect("flushed before the writer drops")
+/// });
+/// write!(out, "buffered").unwrap();
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_inspect<T, F: FnOnce(&mut T)>(data: T, func: F) -> DropGuard<T, impl FnOnce(T)> {
+    guard(data, move |mut data| func(&mut data))
+}
+
+/// Guards a borrowed value, e.g. to restore some state at the end of the scope.
+///
+/// The guard holds the `&mut T`, so methods work directly and assignments need `**`.
+///
+/// ```
+/// use drop_guard::guard_mut;
+///
+/// struct Parser {
+///     depth: usize,
+/// }
+///
+/// let mut parser = Parser { depth: 0 };
+/// {
+///     let mut p = guard_mut(&mut parser, |p| p.depth -= 1);
+///     p.depth += 1;
+///     assert_eq!(1, p.depth);
+/// }
+/// assert_eq!(0, parser.depth);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_mut<'a, T: ?Sized, F: FnOnce(&'a mut T)>(
+    data: &'a mut T,
+    func: F,
+) -> DropGuard<&'a mut T, F> {
+    guard(data, func)
+}
+
+/// Guards the value with a callback that is asserted to be unwind safe.
+///
+/// A `DropGuard` is `UnwindSafe` if both the value and the callback are. Closures
+/// capturing `&mut` references are not, use this constructor if you take
+/// responsibility for the state they touch when a panic unwinds.
+///
+/// ```
+/// use drop_guard::guard_unwind_safe;
+/// use std::panic::catch_unwind;
+///
+/// let mut log = Vec::new();
+/// let g = guard_unwind_safe(5, |i| log.push(i));
+/// let result = catch_unwind(move || {
+///     assert_eq!(5, *g);
+///     panic!("the guard still runs");
+/// });
+/// assert!(result.is_err());
+/// assert_eq!(vec![5], log);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_unwind_safe<T, F: FnOnce(T)>(
+    data: T,
+    func: F,
+) -> DropGuard<T, impl FnOnce(T) + UnwindSafe + RefUnwindSafe> {
+    let func = AssertUnwindSafe(func);
+    guard(data, move |data| {
+        // move the whole wrapper into the closure, not just the field
+        let func = func;
+        (func.0)(data)
+    })
+}
+
+/// The DropGuard will remain to `Send` and `Sync` from `T`.
+///
+/// # Examples
+///
+/// The `LinkedList<T>` is `Send`.
+/// So the `DropGuard` will be too, but it will not be `Sync`:
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::collections::LinkedList;
+/// use std::thread;
+///
+/// let list: LinkedList<u32> = LinkedList::new();
+///
+/// let a_list = guard(list, |_| {});
+///
+/// // Send the guarded list to another thread
+/// thread::spawn(move || {
+///     assert_eq!(0, a_list.len());
+/// }).join();
+/// ```
+pub struct DropGuard<T, F: FnOnce(T), S: Strategy = Always> {
+    data: ManuallyDrop<T>,
+    func: ManuallyDrop<F>,
+    armed: bool,
+    /// Overrides the global [`PanicPolicy`] for this guard.
+    policy: Option<PanicPolicy>,
+    location: &'static Location<'static>,
+    strategy: PhantomData<fn(S)>,
+}
+
+impl<T: Sized, F: FnOnce(T)> DropGuard<T, F> {
+    /// Creates a new guard taking in your data and a function.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let s = String::from("a commonString");
+    /// let mut s = guard(s, |final_string| println!("s became {} at last", final_string));
+    ///
+    /// // much code and time passes by ...
+    /// *s = "a rainbow".to_string();
+    ///
+    /// // by the end of this function the String will have become a rainbow
+    /// ```
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    #[deprecated(note = "use `drop_guard::guard` that is shorter")]
+    pub fn new(data: T, func: F) -> DropGuard<T, F> {
+        guard(data, func)
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> DropGuard<T, F, S> {
+    /// Creates a guard whose [`Strategy`](trait.Strategy.html) decides if the callback runs.
+    ///
+    /// ```
+    /// use drop_guard::{DropGuard, OnUnwind};
+    ///
+    /// let _rollback = DropGuard::<_, _, OnUnwind>::with_strategy(1, |_| unreachable!());
+    /// ```
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn with_strategy(data: T, func: F) -> DropGuard<T, F, S> {
+        events::created::<T>(None, Location::caller());
+        DropGuard::assemble(data, func, true, Location::caller())
+    }
+
+    /// Defuses the guard: the value is dropped in place, the callback never runs.
+    ///
+    /// Use this once the cleanup became unnecessary, e.g. after a successful commit.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut rolled_back = false;
+    /// {
+    ///     let transaction = guard(vec![1, 2, 3], |_| rolled_back = true);
+    ///     // ... the work succeeded
+    ///     transaction.cancel();
+    /// }
+    /// assert!(!rolled_back);
+    /// ```
+    #[inline]
+    pub fn cancel(mut self) {
+        self.disarm();
+    }
+
+    /// Runs the callback right now instead of at the end of the scope.
+    ///
+    /// The guard is consumed, so the callback can not run a second time.
+    /// Only disarming prevents it, the [`Strategy`](trait.Strategy.html) is not consulted.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut log = Vec::new();
+    /// let g = guard("temp file", |name| log.push(format!("removed {}", name)));
+    /// g.trigger();
+    /// assert_eq!(vec!["removed temp file".to_string()], log);
+    /// ```
+    #[inline]
+    pub fn trigger(self) {
+        let mut this = ManuallyDrop::new(self);
+        let run = this.armed;
+        // SAFETY: `this` is never dropped, so the parts are released exactly once
+        unsafe { this.release(run, DropReason::Explicit) }
+    }
+
+    /// Switches the callback off, the value will be dropped without it.
+    ///
+    /// Unlike [`cancel`](#method.cancel) the guard stays alive and can be [rearmed](#method.rearm).
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut rolled_back = false;
+    /// {
+    ///     let mut g = guard(vec!["step"], |_| rolled_back = true);
+    ///     g.push("another step");
+    ///     // everything worked, commit
+    ///     g.disarm();
+    /// }
+    /// assert!(!rolled_back);
+    /// ```
+    #[inline]
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+
+    /// Switches the callback back on after a [`disarm`](#method.disarm).
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut rolled_back = false;
+    /// {
+    ///     let mut g = guard(vec!["step"], |_| rolled_back = true);
+    ///     g.disarm();
+    ///     // a late failure after all
+    ///     g.rearm();
+    /// }
+    /// assert!(rolled_back);
+    /// ```
+    #[inline]
+    pub fn rearm(&mut self) {
+        self.armed = true;
+    }
+
+    /// Tells whether the guard is armed, i.e. not [disarmed](#method.disarm).
+    ///
+    /// The [`Strategy`](trait.Strategy.html) of the guard still decides on drop whether the
+    /// callback runs, an armed `OnSuccess` guard skips it while unwinding.
+    ///
+    /// There is no `has_fired`: a `DropGuard` is consumed when its callback fires,
+    /// so a living guard never has fired yet.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut g = guard(0, |_| {});
+    /// assert!(g.is_armed());
+    ///
+    /// g.disarm();
+    /// assert!(!g.is_armed());
+    /// ```
+    #[inline]
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// The source location where this guard was created.
+    ///
+    /// It is also reported if the callback panics.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let g = guard(0, |_| {});
+    /// assert_eq!(file!(), g.created_at().file());
+    /// assert_eq!(line!() - 2, g.created_at().line());
+    /// ```
+    #[inline]
+    pub fn created_at(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Decides what happens if the callback panics, instead of the global
+    /// [`PanicPolicy`](enum.PanicPolicy.html) set with [`set_panic_policy`](fn.set_panic_policy.html).
+    ///
+    /// ```
+    /// use drop_guard::{guard, PanicPolicy};
+    ///
+    /// {
+    ///     let _g = guard("socket", |_| panic!("close failed"))
+    ///         .with_panic_policy(PanicPolicy::LogAndContinue);
+    /// }
+    /// println!("still shutting down");
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn with_panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Swaps the callback for a new one and returns the previous callback.
+    ///
+    /// Every closure has its own type, so to switch between different closures
+    /// use function pointers or boxed closures as `F`.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut g = guard(
+    ///     vec!["step 1"],
+    ///     Box::new(|steps: Vec<&str>| println!("small rollback of {:?}", steps)) as Box<dyn FnOnce(_)>,
+    /// );
+    ///
+    /// g.push("step 2");
+    /// let _previous = g.replace_callback(Box::new(|steps| println!("big rollback of {:?}", steps)));
+    /// ```
+    #[inline]
+    pub fn replace_callback(&mut self, func: F) -> F {
+        std::mem::replace(&mut *self.func, func)
+    }
+
+    /// Appends another callback that runs after the current one.
+    ///
+    /// The current callback consumes the value, so the appended callback only
+    /// sees what it captured itself.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// {
+    ///     let g = guard(1, |i| log.borrow_mut().push(format!("undo step {}", i)));
+    ///     // later we discover one more thing to clean up
+    ///     let _g = g.and_then(|| log.borrow_mut().push("close connection".to_string()));
+    /// }
+    /// assert_eq!(vec!["undo step 1", "close connection"], *log.borrow());
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn and_then<G: FnOnce()>(self, then: G) -> DropGuard<T, impl FnOnce(T), S> {
+        self.rebuild(|data, func| {
+            (data, move |data| {
+                func(data);
+                then();
+            })
+        })
+    }
+
+    /// Registers a callback that runs before the current one.
+    ///
+    /// It gets mutable access to the value before the current callback consumes it.
+    /// Prepending several times runs the callbacks in LIFO order.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// {
+    ///     let g = guard(vec![1], |v| log.borrow_mut().push(format!("release {:?}", v)));
+    ///     let g = g.prepend(|v| v.push(2));
+    ///     let _g = g.prepend(|v| log.borrow_mut().push(format!("flush {:?}", v)));
+    /// }
+    /// assert_eq!(vec!["flush [1]", "release [1, 2]"], *log.borrow());
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn prepend<G: FnOnce(&mut T)>(self, before: G) -> DropGuard<T, impl FnOnce(T), S> {
+        self.rebuild(|data, func| {
+            (data, move |mut data| {
+                before(&mut data);
+                func(data);
+            })
+        })
+    }
+
+    /// Transforms the guarded value while keeping the cleanup.
+    ///
+    /// `wrap` runs right away, `unwrap` runs at drop time to hand the original
+    /// type back to the callback.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::io::{BufRead, BufReader, Cursor};
+    ///
+    /// let g = guard(Cursor::new("line one\nline two"), |c| println!("read {} bytes", c.position()));
+    /// let mut g = g.map(BufReader::new, BufReader::into_inner);
+    ///
+    /// let mut line = String::new();
+    /// g.read_line(&mut line).unwrap();
+    /// assert_eq!("line one\n", line);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn map<U, W, B>(self, wrap: W, unwrap: B) -> DropGuard<U, impl FnOnce(U), S>
+    where
+        W: FnOnce(T) -> U,
+        B: FnOnce(U) -> T,
+    {
+        self.rebuild(|data, func| (wrap(data), move |data| func(unwrap(data))))
+    }
+
+    /// Combines two guards into one over both values.
+    ///
+    /// Like locals the guards are released in reverse order: first `other`, then `self`.
+    /// Both keep their own [`Strategy`](trait.Strategy.html).
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// {
+    ///     let lock = guard("lock", |l| log.borrow_mut().push(l));
+    ///     let file = guard("file", |f| log.borrow_mut().push(f));
+    ///     let both = lock.zip(file);
+    ///     assert_eq!(("lock", "file"), *both);
+    /// }
+    /// assert_eq!(vec!["file", "lock"], *log.borrow());
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn zip<U, G: FnOnce(U), S2: Strategy>(
+        self,
+        other: DropGuard<U, G, S2>,
+    ) -> DropGuard<(T, U), impl FnOnce((T, U))> {
+        let (armed, policy, location) = (self.armed, self.policy, self.location);
+        let (other_armed, other_policy, other_location) =
+            (other.armed, other.policy, other.location);
+        let (data, func) = self.into_parts();
+        let (other_data, other_func) = other.into_parts();
+
+        let release = move |(data, other_data)| {
+            // locals drop in reverse order, even if the first callback panics
+            let mut _first = DropGuard::<_, _, S>::assemble(data, func, armed, location);
+            _first.policy = policy;
+            let mut _second = DropGuard::<_, _, S2>::assemble(
+                other_data,
+                other_func,
+                other_armed,
+                other_location,
+            );
+            _second.policy = other_policy;
+        };
+        DropGuard::assemble((data, other_data), release, true, location)
+    }
+
+    /// Dismantles the guard without running the callback and returns both parts.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let g = guard(String::from("config"), |s| println!("saving {}", s));
+    /// let (data, func) = g.into_parts();
+    ///
+    /// // hand the pieces to someone else who builds their own guard
+    /// let g = guard(data, func);
+    /// assert_eq!("config", *g);
+    /// ```
+    #[inline]
+    pub fn into_parts(self) -> (T, F) {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so both parts are moved out exactly once
+        unsafe {
+            (
+                ManuallyDrop::take(&mut this.data),
+                ManuallyDrop::take(&mut this.func),
+            )
+        }
+    }
+
+    /// Leaks the value without running its destructor and discards the callback without running it.
+    ///
+    /// Use this to hand the value over to an arena or FFI code that now owns it.
+    /// Only the callback itself is dropped, releasing what it captured.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::rc::Rc;
+    ///
+    /// let handle = Rc::new("ffi handle");
+    /// let g = guard(handle.clone(), |_| panic!("never runs"));
+    /// g.forget();
+    /// assert_eq!(2, Rc::strong_count(&handle));
+    /// ```
+    #[inline]
+    pub fn forget(self) {
+        std::mem::forget(self.into_parts().0)
+    }
+
+    /// Runs `body` with the value and the callback right after it, like a `finally` block.
+    ///
+    /// The callback runs even if `body` panics.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let mut closed = false;
+    /// let len = guard(vec![1, 2], |_| closed = true).scope(|v| {
+    ///     v.push(3);
+    ///     v.len()
+    /// });
+    /// assert_eq!(3, len);
+    /// assert!(closed);
+    /// ```
+    #[inline]
+    pub fn scope<R, B: FnOnce(&mut T) -> R>(mut self, body: B) -> R {
+        body(&mut self)
+    }
+
+    /// Discards the callback without running it and leaks the value, like `Box::leak`.
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    ///
+    /// let config = guard(vec![String::from("setting")], |_| panic!("never runs"));
+    /// let config: &'static mut Vec<String> = config.leak();
+    /// config.push(String::from("another setting"));
+    /// ```
+    #[inline]
+    pub fn leak(self) -> &'static mut T
+    where
+        T: 'static,
+    {
+        Box::leak(Box::new(self.into_parts().0))
+    }
+
+    /// Builds a new guard from the parts of this one, keeping its state and creation site.
+    #[inline]
+    fn rebuild<U, G, B>(self, build: B) -> DropGuard<U, G, S>
+    where
+        G: FnOnce(U),
+        B: FnOnce(T, F) -> (U, G),
+    {
+        let (armed, policy, location) = (self.armed, self.policy, self.location);
+        let (data, func) = self.into_parts();
+        let (data, func) = build(data, func);
+        let mut rebuilt = DropGuard::assemble(data, func, armed, location);
+        rebuilt.policy = policy;
+        rebuilt
+    }
+
+    /// Puts a guard together from all of its parts.
+    #[inline]
+    fn assemble(data: T, func: F, armed: bool, location: &'static Location<'static>) -> Self {
+        DropGuard {
+            data: ManuallyDrop::new(data),
+            func: ManuallyDrop::new(func),
+            armed,
+            policy: None,
+            location,
+            strategy: PhantomData,
+        }
+    }
+
+    /// Takes both parts out and runs the callback for `reason` if `run` is set.
+    ///
+    /// # Safety
+    ///
+    /// Must be called at most once, the parts must not be used afterwards.
+    #[inline]
+    unsafe fn release(&mut self, run: bool, reason: DropReason) {
+        let data = ManuallyDrop::take(&mut self.data);
+        let func = ManuallyDrop::take(&mut self.func);
+        if !run {
+            return;
+        }
+        if self.policy.is_none() && !events::instrumented() && !thread::panicking() {
+            // nothing observes the callback and a panic of it may simply unwind
+            let note = PanicNote {
+                location: self.location,
+                was_panicking: false,
+            };
+            func(data);
+            mem::forget(note);
+        } else {
+            name::reset_failed();
+            let _note = PanicNote::new(self.location);
+            let started = events::start();
+            let ((), label) = name::capture(|| {
+                unwind::run(self.location, self.policy, || {
+                    reason::scoped(reason, || func(data))
+                })
+            });
+            events::dropped::<T>(started, label, self.location);
+        }
+    }
+
+    /// Projects a pinned guard to its value, which is never pinned structurally.
+    #[inline]
+    fn data_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T>
+    where
+        T: Unpin,
+    {
+        // SAFETY: `T: Unpin` and `F` is never projected, so nothing relies on the pin
+        Pin::new(unsafe { &mut *self.get_unchecked_mut().data })
+    }
+}
+
+impl<T, F1, F2, S1, S2> DropGuard<DropGuard<T, F1, S1>, F2, S2>
+where
+    F1: FnOnce(T),
+    F2: FnOnce(DropGuard<T, F1, S1>),
+    S1: Strategy,
+    S2: Strategy,
+{
+    /// Merges nested guards into one that derefs straight to the value.
+    ///
+    /// On drop the outer callback runs first and receives the inner guard just like
+    /// before, so the inner callback runs when the outer one is done with it.
+    /// Both keep their own [`Strategy`](trait.Strategy.html).
+    ///
+    /// ```
+    /// use drop_guard::guard;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// {
+    ///     let inner = guard(1, |i| log.borrow_mut().push(format!("inner {}", i)));
+    ///     let outer = guard(inner, |_| log.borrow_mut().push("outer".to_string()));
+    ///     let flat = outer.flatten();
+    ///     assert_eq!(1, *flat);
+    /// }
+    /// assert_eq!(vec!["outer", "inner 1"], *log.borrow());
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn flatten(self) -> DropGuard<T, impl FnOnce(T)> {
+        let (outer_armed, outer_policy, location) = (self.armed, self.policy, self.location);
+        let (inner, outer_func) = self.into_parts();
+        let (inner_armed, inner_policy, inner_location) =
+            (inner.armed, inner.policy, inner.location);
+        let (data, inner_func) = inner.into_parts();
+
+        let func = move |data| {
+            let mut inner = DropGuard::assemble(data, inner_func, inner_armed, inner_location);
+            inner.policy = inner_policy;
+            if outer_armed && S2::should_run() {
+                outer_func(inner);
+            }
+        };
+        let mut flat = DropGuard::assemble(data, func, true, location);
+        flat.policy = outer_policy;
+        flat
+    }
+}
+
+impl<T> FnGuard<T> {
+    /// Supplies the callback of a guard created with [`guard_noop`](fn.guard_noop.html).
+    ///
+    /// The placeholder callback is discarded, the armed state and creation site are kept.
+    #[must_use]
+    #[inline]
+    pub fn attach<G: FnOnce(T)>(self, func: G) -> DropGuard<T, G> {
+        self.rebuild(|data, _| (data, func))
+    }
+}
+
+/// Use the captured value.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let val = guard(42usize, |_| {});
+/// assert_eq!(42, *val);
+/// ```
+impl<T, F: FnOnce(T), S: Strategy> Deref for DropGuard<T, F, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+/// Modify the captured value.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let mut val = guard(vec![2, 3, 4], |_| {});
+/// assert_eq!(3, val.len());
+///
+/// val.push(5);
+/// assert_eq!(4, val.len());
+/// ```
+impl<T, F: FnOnce(T), S: Strategy> DerefMut for DropGuard<T, F, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+/// Pass the guard to APIs taking `impl AsRef<_>`.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::path::{Path, PathBuf};
+///
+/// fn extension(p: impl AsRef<Path>) -> Option<String> {
+///     Some(p.as_ref().extension()?.to_str()?.to_string())
+/// }
+///
+/// let tmp = guard(PathBuf::from("/tmp/report.pdf"), |_| {});
+/// assert_eq!(Some("pdf".to_string()), extension(&tmp));
+/// assert_eq!(Some("pdf".to_string()), extension(tmp));
+/// ```
+impl<T: AsRef<U>, U: ?Sized, F: FnOnce(T), S: Strategy> AsRef<U> for DropGuard<T, F, S> {
+    fn as_ref(&self) -> &U {
+        (*self.data).as_ref()
+    }
+}
+
+/// Pass the guard to APIs taking `impl AsMut<_>`.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// fn zero(mut buf: impl AsMut<[u8]>) {
+///     buf.as_mut().iter_mut().for_each(|b| *b = 0);
+/// }
+///
+/// let mut buf = guard(vec![1u8, 2, 3], |_| {});
+/// zero(&mut buf);
+/// assert_eq!(vec![0, 0, 0], *buf);
+/// ```
+impl<T: AsMut<U>, U: ?Sized, F: FnOnce(T), S: Strategy> AsMut<U> for DropGuard<T, F, S> {
+    fn as_mut(&mut self) -> &mut U {
+        (*self.data).as_mut()
+    }
+}
+
+/// Pass the guard to APIs taking `impl Borrow<T>`.
+///
+/// Forwarding to every `Borrow<U>` of `T` would conflict with the blanket `Borrow<T> for T`.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::borrow::Borrow;
+///
+/// fn shout(s: impl Borrow<String>) -> String {
+///     s.borrow().to_uppercase()
+/// }
+///
+/// let name = guard(String::from("ferris"), |_| {});
+/// assert_eq!("FERRIS", shout(name));
+/// ```
+impl<T, F: FnOnce(T), S: Strategy> Borrow<T> for DropGuard<T, F, S> {
+    fn borrow(&self) -> &T {
+        &self.data
+    }
+}
+
+/// Pass the guard to APIs taking `impl BorrowMut<T>`.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::borrow::BorrowMut;
+///
+/// fn clear(mut v: impl BorrowMut<Vec<u8>>) {
+///     v.borrow_mut().clear();
+/// }
+///
+/// let mut data = guard(vec![1u8, 2, 3], |v| assert!(v.is_empty()));
+/// clear(data);
+/// ```
+impl<T, F: FnOnce(T), S: Strategy> BorrowMut<T> for DropGuard<T, F, S> {
+    fn borrow_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+/// Compare the captured values, the callbacks are ignored.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let a = guard(1, |_| {});
+/// let b = guard(1, |_| println!("a different callback"));
+/// assert!(a == b);
+/// assert!(a == 1);
+/// ```
+impl<T: PartialEq, F: FnOnce(T), G: FnOnce(T), S: Strategy, S2: Strategy>
+    PartialEq<DropGuard<T, G, S2>> for DropGuard<T, F, S>
+{
+    fn eq(&self, other: &DropGuard<T, G, S2>) -> bool {
+        *self.data == *other.data
+    }
+}
+
+impl<T: PartialEq, F: FnOnce(T), S: Strategy> PartialEq<T> for DropGuard<T, F, S> {
+    fn eq(&self, other: &T) -> bool {
+        *self.data == *other
+    }
+}
+
+impl<T: Eq, F: FnOnce(T), S: Strategy> Eq for DropGuard<T, F, S> {}
+
+/// Order by the captured values, the callbacks are ignored.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let mut v: Vec<_> = vec![3, 1, 2].into_iter().map(|i| guard(i, |_| {})).collect();
+/// v.sort();
+/// assert_eq!(vec![1, 2, 3], v.iter().map(|g| **g).collect::<Vec<_>>());
+/// assert!(v[0] < 2);
+/// ```
+impl<T: PartialOrd, F: FnOnce(T), G: FnOnce(T), S: Strategy, S2: Strategy>
+    PartialOrd<DropGuard<T, G, S2>> for DropGuard<T, F, S>
+{
+    fn partial_cmp(&self, other: &DropGuard<T, G, S2>) -> Option<Ordering> {
+        (*self.data).partial_cmp(&*other.data)
+    }
+}
+
+impl<T: PartialOrd, F: FnOnce(T), S: Strategy> PartialOrd<T> for DropGuard<T, F, S> {
+    fn partial_cmp(&self, other: &T) -> Option<Ordering> {
+        (*self.data).partial_cmp(other)
+    }
+}
+
+impl<T: Ord, F: FnOnce(T), S: Strategy> Ord for DropGuard<T, F, S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (*self.data).cmp(&*other.data)
+    }
+}
+
+/// Hash like the captured value, so guards can be looked up by their value.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::collections::HashSet;
+///
+/// let mut set = HashSet::new();
+/// set.insert(guard(42, |_| {}));
+/// assert!(set.contains(&42));
+/// ```
+impl<T: Hash, F: FnOnce(T), S: Strategy> Hash for DropGuard<T, F, S> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (*self.data).hash(state)
+    }
+}
+
+/// Print the captured value and whether the callback will run.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let mut val = guard(vec![1, 2], |_| {});
+/// assert_eq!("DropGuard { data: [1, 2], armed: true }", format!("{:?}", val));
+///
+/// val.disarm();
+/// assert_eq!("DropGuard { data: [1, 2], armed: false }", format!("{:?}", val));
+/// ```
+impl<T: fmt::Debug, F: FnOnce(T), S: Strategy> fmt::Debug for DropGuard<T, F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropGuard")
+            .field("data", &*self.data)
+            .field("armed", &self.armed)
+            .finish()
+    }
+}
+
+/// Print the captured value like `T` does.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let name = guard(String::from("ferris"), |_| {});
+/// assert_eq!("hello ferris", format!("hello {}", name));
+/// ```
+impl<T: fmt::Display, F: FnOnce(T), S: Strategy> fmt::Display for DropGuard<T, F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.data, f)
+    }
+}
+
+/// Propagate a guarded error like the error itself.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::error::Error;
+///
+/// fn parse() -> Result<u8, Box<dyn Error>> {
+///     let n = "256".parse::<u8>().map_err(|e| guard(e, |e| println!("dropped {}", e)))?;
+///     Ok(n)
+/// }
+///
+/// let err = parse().unwrap_err();
+/// assert_eq!("number too large to fit in target type", err.to_string());
+/// ```
+impl<E: Error, F: FnOnce(E), S: Strategy> Error for DropGuard<E, F, S> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.data.source()
     }
 }
 
-/// The DropGuard will remain to `Send` and `Sync` from `T`.
+/// Iterate a guarded iterator directly.
 ///
-/// # Examples
+/// Because the guard is an `Iterator` itself, `&mut DropGuard` iterates the guarded iterator
+/// too and can not forward to `&mut T: IntoIterator`. Use `iter_mut()` for guarded collections.
 ///
-/// The `LinkedList<T>` is `Send`.
-/// So the `DropGuard` will be too, but it will not be `Sync`:
+/// ```
+/// use drop_guard::guard;
+///
+/// let mut consumed = 0;
+/// {
+///     let lines = guard("a\nb\nc".lines(), |rest| consumed = 3 - rest.count());
+///     for line in lines.take(2) {
+///         assert!(line == "a" || line == "b");
+///     }
+/// }
+/// assert_eq!(2, consumed);
+/// ```
+impl<T: Iterator, F: FnOnce(T), S: Strategy> Iterator for DropGuard<T, F, S> {
+    type Item = T::Item;
+
+    fn next(&mut self) -> Option<T::Item> {
+        self.data.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.data.size_hint()
+    }
+}
+
+/// Await a guarded future directly, the callback gets whatever is left of it.
+///
+/// The callback takes the future by value, so it can not be pinned inside the guard and
+/// has to be `Unpin`. Box it or use a [`PinDropGuard`](struct.PinDropGuard.html) otherwise.
 ///
 /// ```
 /// use drop_guard::guard;
-/// use std::collections::LinkedList;
-/// use std::thread;
+/// use std::future::{ready, Future};
+/// use std::task::{Context, Poll, Waker};
 ///
-/// let list: LinkedList<u32> = LinkedList::new();
+/// let mut fut = guard(ready(42), |_| println!("future dropped"));
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert_eq!(Poll::Ready(42), std::pin::Pin::new(&mut fut).poll(&mut cx));
+/// ```
+impl<T: Future + Unpin, F: FnOnce(T), S: Strategy> Future for DropGuard<T, F, S> {
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T::Output> {
+        self.data_pin_mut().poll(cx)
+    }
+}
+
+/// Iterate a guarded collection by reference.
 ///
-/// let a_list = guard(list, |_| {});
+/// ```
+/// use drop_guard::guard;
 ///
-/// // Send the guarded list to another thread
-/// thread::spawn(move || {
-///     assert_eq!(0, a_list.len());
-/// }).join();
+/// let v = guard(vec![1, 2, 3], |_| {});
+/// let mut sum = 0;
+/// for i in &v {
+///     sum += i;
+/// }
+/// assert_eq!(6, sum);
 /// ```
-pub struct DropGuard<T, F: FnMut(T)> {
-    data: Option<T>,
-    func: Box<F>,
+impl<'a, T, F: FnOnce(T), S: Strategy> IntoIterator for &'a DropGuard<T, F, S>
+where
+    &'a T: IntoIterator,
+{
+    type Item = <&'a T as IntoIterator>::Item;
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (&*self.data).into_iter()
+    }
 }
 
-impl<T: Sized, F: FnMut(T)> DropGuard<T, F> {
-    /// Creates a new guard taking in your data and a function.
-    ///
-    /// ```
-    /// use drop_guard::guard;
-    ///
-    /// let s = String::from("a commonString");
-    /// let mut s = guard(s, |final_string| println!("s became {} at last", final_string));
-    ///
-    /// // much code and time passes by ...
-    /// *s = "a rainbow".to_string();
-    ///
-    /// // by the end of this function the String will have become a rainbow
-    /// ```
-    #[must_use]
-    #[inline]
-    #[deprecated(note = "use `drop_guard::guard` that is shorter")]
-    pub fn new(data: T, func: F) -> DropGuard<T, F> {
-        guard(data, func)
+/// Index into the captured value.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::collections::HashMap;
+///
+/// let v = guard(vec![1, 2, 3], |_| {});
+/// assert_eq!(2, v[1]);
+///
+/// let mut m = HashMap::new();
+/// m.insert("key", "value");
+/// let m = guard(m, |_| {});
+/// assert_eq!("value", m[&"key"]);
+/// ```
+impl<T: Index<I>, I, F: FnOnce(T), S: Strategy> Index<I> for DropGuard<T, F, S> {
+    type Output = T::Output;
+
+    fn index(&self, index: I) -> &T::Output {
+        &self.data[index]
     }
 }
 
-/// Use the captured value.
+/// Modify the captured value by index.
 ///
 /// ```
 /// use drop_guard::guard;
 ///
-/// let val = guard(42usize, |_| {});
-/// assert_eq!(42, *val);
+/// let mut v = guard(vec![1, 2, 3], |_| {});
+/// v[1] = 5;
+/// assert_eq!(vec![1, 5, 3], *v);
 /// ```
-impl<T, F: FnMut(T)> Deref for DropGuard<T, F> {
-    type Target = T;
+impl<T: IndexMut<I>, I, F: FnOnce(T), S: Strategy> IndexMut<I> for DropGuard<T, F, S> {
+    fn index_mut(&mut self, index: I) -> &mut T::Output {
+        &mut self.data[index]
+    }
+}
 
-    fn deref(&self) -> &T {
-        &self.data.as_ref().expect("the data is here until the drop")
+/// Read from a guarded reader.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::io::{Cursor, Read};
+///
+/// let mut input = guard(Cursor::new(b"hello"), |_| println!("closing input"));
+/// let mut s = String::new();
+/// input.read_to_string(&mut s).unwrap();
+/// assert_eq!("hello", s);
+/// ```
+impl<T: io::Read, F: FnOnce(T), S: Strategy> io::Read for DropGuard<T, F, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.data.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.data.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.data.read_to_string(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.data.read_exact(buf)
     }
 }
 
-/// Modify the captured value.
+/// Read lines from a guarded buffered reader.
 ///
 /// ```
 /// use drop_guard::guard;
+/// use std::io::{BufRead, Cursor};
 ///
-/// let mut val = guard(vec![2, 3, 4], |_| {});
-/// assert_eq!(3, val.len());
+/// let input = guard(Cursor::new("a\nb\n"), |_| {});
+/// let lines: Vec<String> = input.lines().map(Result::unwrap).collect();
+/// assert_eq!(vec!["a", "b"], lines);
+/// ```
+impl<T: io::BufRead, F: FnOnce(T), S: Strategy> io::BufRead for DropGuard<T, F, S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.data.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.data.consume(amt)
+    }
+
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.data.read_until(byte, buf)
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.data.read_line(buf)
+    }
+}
+
+/// Write to a guarded writer.
 ///
-/// val.push(5);
-/// assert_eq!(4, val.len());
 /// ```
-impl<T, F: FnMut(T)> DerefMut for DropGuard<T, F> {
-    fn deref_mut(&mut self) -> &mut T {
-        self.data.as_mut().expect("the data is here until the drop")
+/// use drop_guard::guard;
+/// use std::io::Write;
+///
+/// let mut out = guard(Vec::new(), |buf| assert_eq!(b"hello world", &buf[..]));
+/// write!(out, "hello {}", "world").unwrap();
+/// ```
+impl<T: io::Write, F: FnOnce(T), S: Strategy> io::Write for DropGuard<T, F, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    // `is_write_vectored` is still unstable and cannot be forwarded
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.data.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.data.flush()
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.data.write_all(buf)
+    }
+
+    fn write_fmt(&mut self, fmt: fmt::Arguments<'_>) -> io::Result<()> {
+        self.data.write_fmt(fmt)
+    }
+}
+
+/// Seek in a guarded stream.
+///
+/// ```
+/// use drop_guard::guard;
+/// use std::io::{Cursor, Seek, SeekFrom};
+///
+/// let mut c = guard(Cursor::new(vec![0u8; 8]), |_| {});
+/// assert_eq!(6, c.seek(SeekFrom::End(-2)).unwrap());
+/// ```
+impl<T: io::Seek, F: FnOnce(T), S: Strategy> io::Seek for DropGuard<T, F, S> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.data.rewind()
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.data.stream_position()
+    }
+
+    fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
+        self.data.seek_relative(offset)
     }
 }
 
@@ -131,13 +1622,42 @@ impl<T, F: FnMut(T)> DerefMut for DropGuard<T, F> {
 /// });
 /// assert_eq!(42, *val);
 /// ```
-impl<T, F: FnMut(T)> Drop for DropGuard<T, F> {
+impl<T, F: FnOnce(T), S: Strategy> Drop for DropGuard<T, F, S> {
     fn drop(&mut self) {
-        let mut data: Option<T> = None;
-        std::mem::swap(&mut data, &mut self.data);
+        let run = self.armed && S::should_run();
+        let reason = if thread::panicking() {
+            DropReason::Panic
+        } else {
+            DropReason::ScopeExit
+        };
+        // SAFETY: drop runs at most once and the parts are not touched afterwards
+        unsafe { self.release(run, reason) }
+    }
+}
+
+/// Points at the creation site of a guard whose callback panicked.
+struct PanicNote {
+    location: &'static Location<'static>,
+    was_panicking: bool,
+}
 
-        let ref mut f = self.func;
-        f(data.expect("the data is here until the drop"));
+impl PanicNote {
+    fn new(location: &'static Location<'static>) -> PanicNote {
+        PanicNote {
+            location,
+            was_panicking: thread::panicking(),
+        }
+    }
+}
+
+impl Drop for PanicNote {
+    fn drop(&mut self) {
+        if !self.was_panicking && thread::panicking() {
+            report::report(format_args!(
+                "note: the callback of the {} panicked",
+                name::failed(self.location)
+            ));
+        }
     }
 }
 
@@ -208,6 +1728,257 @@ mod tests {
         assert_eq!(42usize, a.load(Ordering::Relaxed));
     }
 
+    #[test]
+    fn fn_once_callback() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        {
+            // the sender is moved into the callback and consumed there
+            let _g = guard(5usize, move |i| {
+                tx.send(i).unwrap();
+                drop(tx);
+            });
+        }
+        assert_eq!(vec![5usize], rx.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cancel_skips_callback() {
+        let a = Arc::new(AtomicUsize::new(9));
+        let data = Arc::new(());
+        {
+            let a = a.clone();
+            let g = guard(data.clone(), move |_| a.store(42, Ordering::Relaxed));
+            assert_eq!(2, Arc::strong_count(&data));
+            g.cancel();
+            assert_eq!(1, Arc::strong_count(&data));
+        }
+        assert_eq!(9usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn trigger_runs_once() {
+        let a = Arc::new(AtomicUsize::new(0));
+        {
+            let g = guard(a.clone(), |i| {
+                i.fetch_add(1, Ordering::Relaxed);
+            });
+            g.trigger();
+            assert_eq!(1usize, a.load(Ordering::Relaxed));
+        }
+        assert_eq!(1usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn replace_callback_runs_new_one() {
+        fn store_1(a: Arc<AtomicUsize>) {
+            a.store(1, Ordering::Relaxed)
+        }
+        fn store_2(a: Arc<AtomicUsize>) {
+            a.store(2, Ordering::Relaxed)
+        }
+
+        let a = Arc::new(AtomicUsize::new(0));
+        {
+            let mut g = guard(a.clone(), store_1 as fn(_));
+            let old = g.replace_callback(store_2);
+            old(a.clone());
+            assert_eq!(1usize, a.load(Ordering::Relaxed));
+        }
+        assert_eq!(2usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn and_then_runs_in_order() {
+        let order = std::cell::RefCell::new(Vec::new());
+        {
+            let _g = guard(1, |i| order.borrow_mut().push(i))
+                .and_then(|| order.borrow_mut().push(2))
+                .and_then(|| order.borrow_mut().push(3));
+            assert!(order.borrow().is_empty());
+        }
+        assert_eq!(vec![1, 2, 3], *order.borrow());
+    }
+
+    #[test]
+    fn prepend_runs_lifo() {
+        let order = std::cell::RefCell::new(Vec::new());
+        {
+            let _g = guard(1, |i| order.borrow_mut().push(i))
+                .prepend(|_| order.borrow_mut().push(2))
+                .prepend(|_| order.borrow_mut().push(3));
+        }
+        assert_eq!(vec![3, 2, 1], *order.borrow());
+    }
+
+    #[test]
+    fn map_keeps_callback() {
+        let a = Arc::new(AtomicUsize::new(0));
+        {
+            let g = guard(5usize, |i| a.store(i, Ordering::Relaxed));
+            let mut g = g.map(|i| vec![i], |v| v.iter().sum());
+            g.push(37);
+        }
+        assert_eq!(42usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn try_guard_error_skips_callback() {
+        let mut called = false;
+        let r = try_guard(|| Err::<usize, _>("no resource"), |_| called = true);
+        assert_eq!(Some("no resource"), r.err());
+        assert!(!called);
+    }
+
+    #[test]
+    fn guard_if_true_runs_callback() {
+        let mut called = false;
+        {
+            let _g = guard_if(true, 0, |_| called = true);
+        }
+        assert!(called);
+    }
+
+    #[test]
+    fn disarmed_drops_value() {
+        let a = Arc::new(AtomicUsize::new(9));
+        let data = Arc::new(());
+        {
+            let a = a.clone();
+            let mut g = guard(data.clone(), move |_| a.store(42, Ordering::Relaxed));
+            g.disarm();
+            g.rearm();
+            assert!(g.is_armed());
+            g.disarm();
+        }
+        assert_eq!(1, Arc::strong_count(&data));
+        assert_eq!(9usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn combinators_keep_disarmed() {
+        let mut called = false;
+        {
+            let mut g = guard(1, |_| called = true);
+            g.disarm();
+            let _g = g.prepend(|_| {}).and_then(|| {}).map(|i| i, |i| i);
+        }
+        assert!(!called);
+    }
+
+    #[test]
+    fn into_parts_keeps_callback_state() {
+        let mut calls = 0;
+        let g = guard(7usize, |_| calls += 1);
+        let (data, func) = g.into_parts();
+        assert_eq!(7usize, data);
+        {
+            let _g = guard(data, func);
+        }
+        assert_eq!(1, calls);
+    }
+
+    #[test]
+    fn no_layout_overhead() {
+        use std::mem::size_of;
+        assert_eq!(
+            size_of::<(usize, fn(usize), bool, &Location)>(),
+            size_of::<DropGuard<usize, fn(usize)>>()
+        );
+    }
+
+    #[test]
+    fn combinators_keep_creation_site() {
+        let g = guard(1, |_| {});
+        let line = line!() - 1;
+        let g = g.and_then(|| {}).prepend(|_| {});
+        assert_eq!(line, g.created_at().line());
+    }
+
+    #[test]
+    fn iterate_by_mut_ref() {
+        let mut it = guard(0..5, |rest| assert_eq!(3..5, rest));
+        let firsts: Vec<_> = (&mut it).take(3).collect();
+        assert_eq!(vec![0, 1, 2], firsts);
+    }
+
+    #[test]
+    fn await_guarded_future() {
+        let mut cancelled = false;
+        {
+            let mut fut = guard(Box::pin(std::future::pending::<()>()), |_| cancelled = true);
+            let mut cx = Context::from_waker(std::task::Waker::noop());
+            assert_eq!(Poll::Pending, Pin::new(&mut fut).poll(&mut cx));
+        }
+        assert!(cancelled);
+    }
+
+    #[test]
+    fn inspect_then_drop() {
+        let data = Arc::new(());
+        let mut seen = 0;
+        {
+            let _g = guard_inspect(data.clone(), |d| seen = Arc::strong_count(d));
+        }
+        assert_eq!(2, seen);
+        assert_eq!(1, Arc::strong_count(&data));
+    }
+
+    #[test]
+    fn scope_cleans_up_on_panic() {
+        let a = Arc::new(AtomicUsize::new(0));
+        let g = guard(a.clone(), |a| a.store(42, Ordering::Relaxed));
+        let r = std::panic::catch_unwind(move || g.scope(|_| panic!("body failed")));
+        assert!(r.is_err());
+        assert_eq!(42usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn flatten_keeps_inner_when_outer_disarmed() {
+        let mut inner_called = false;
+        {
+            let inner = guard(1, |_| inner_called = true);
+            let mut outer = guard(inner, |_| unreachable!());
+            outer.disarm();
+            let _flat = outer.flatten();
+        }
+        assert!(inner_called);
+    }
+
+    #[test]
+    fn on_unwind_runs_when_panicking() {
+        let a = Arc::new(AtomicUsize::new(0));
+        let b = a.clone();
+        let r = std::panic::catch_unwind(move || {
+            let _g = guard_on_unwind(b, |b| b.store(42, Ordering::Relaxed));
+            panic!("fail");
+        });
+        assert!(r.is_err());
+        assert_eq!(42usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn on_success_skipped_when_panicking() {
+        let a = Arc::new(AtomicUsize::new(0));
+        let b = a.clone();
+        let r = std::panic::catch_unwind(move || {
+            let _g = guard_on_success(b, |b| b.store(42, Ordering::Relaxed));
+            panic!("fail");
+        });
+        assert!(r.is_err());
+        assert_eq!(0usize, a.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn keep_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>(_: T) {}
+        let g = guard(vec![0], |_| {});
+        assert_unwind_safe(g);
+
+        let mut i = 0;
+        let g = guard_unwind_safe(vec![0], |_| i += 1);
+        assert_unwind_safe(g);
+    }
+
     #[test]
     fn keep_sync_shared_data() {
         fn assert_sync<T: Sync>(_: T) {}
@@ -221,4 +1992,52 @@ mod tests {
         let g = guard(vec![0], |_| {});
         assert_send(g);
     }
+
+    #[test]
+    fn with_guard_cleans_up_on_panic() {
+        let a = AtomicUsize::new(0);
+        let r = std::panic::catch_unwind(|| {
+            with_guard(
+                1,
+                |v| a.store(v, Ordering::Relaxed),
+                |_| panic!("body failed"),
+            )
+        });
+        assert!(r.is_err());
+        assert_eq!(1, a.load(Ordering::Relaxed));
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn cleanup_attribute_in_nested_block() {
+        #[cleanup]
+        fn run(log: &mut Vec<i32>) {
+            {
+                #[cleanup(|v| log.push(v))]
+                let value = 1;
+                assert_eq!(1, *value);
+            }
+        }
+
+        let mut log = Vec::new();
+        run(&mut log);
+        assert_eq!(vec![1], log);
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn derive_guarded_in_declared_order() {
+        use std::cell::RefCell;
+
+        thread_local!(static LOG: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) });
+        fn log(v: &mut u8) {
+            LOG.with(|l| l.borrow_mut().push(*v));
+        }
+
+        #[derive(Guarded)]
+        struct Pair(#[on_drop(log)] u8, (), #[on_drop(log)] u8);
+
+        drop(Pair(1, (), 3));
+        LOG.with(|l| assert_eq!(vec![1, 3], *l.borrow()));
+    }
 }
@@ -0,0 +1,153 @@
+//! Labels that tell guards apart in panic messages and logs.
+
+use crate::{events, DropGuard};
+use std::cell::Cell;
+use std::fmt;
+use std::panic::Location;
+
+thread_local! {
+    /// The name of the callback that is currently running on this thread.
+    static CURRENT: Cell<Option<&'static str>> = const { Cell::new(None) };
+    /// The name of the last callback that panicked on this thread.
+    static FAILED: Cell<Option<&'static str>> = const { Cell::new(None) };
+    /// The name of the last callback that ended on this thread.
+    static LAST: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Restores the name of an outer callback and remembers the name if the inner one panics.
+struct Restore {
+    previous: Option<&'static str>,
+    name: &'static str,
+    done: bool,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|c| c.set(self.previous));
+        LAST.with(|l| l.set(Some(self.name)));
+        if !self.done {
+            FAILED.with(|f| f.set(Some(self.name)));
+        }
+    }
+}
+
+fn scoped<R>(name: &'static str, f: impl FnOnce() -> R) -> R {
+    let mut restore = Restore {
+        previous: CURRENT.with(|c| c.replace(Some(name))),
+        name,
+        done: false,
+    };
+    let result = f();
+    restore.done = true;
+    result
+}
+
+/// Forgets the name of a previous panicking callback before the next one runs.
+#[inline]
+pub(crate) fn reset_failed() {
+    FAILED.with(|f| f.set(None));
+}
+
+/// Runs a callback and hands back its name, if it has one.
+pub(crate) fn capture<R>(f: impl FnOnce() -> R) -> (R, Option<&'static str>) {
+    let previous = LAST.with(|l| l.take());
+    let result = f();
+    (result, LAST.with(|l| l.replace(previous)))
+}
+
+/// Describes the guard created at `location` whose callback panicked.
+pub(crate) fn failed(location: &'static Location<'static>) -> Subject {
+    Subject {
+        name: FAILED.with(|f| f.take()),
+        location,
+    }
+}
+
+/// Displays a guard by name and creation site.
+pub(crate) struct Subject {
+    name: Option<&'static str>,
+    location: &'static Location<'static>,
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "DropGuard '{}' created at {}", name, self.location),
+            None => write!(f, "DropGuard created at {}", self.location),
+        }
+    }
+}
+
+/// The name of the [`guard_named`](fn.guard_named.html) callback running on this thread, if any.
+///
+/// Handy to tag log lines written by the callback or by code it calls.
+pub fn current_guard_name() -> Option<&'static str> {
+    CURRENT.with(Cell::get)
+}
+
+/// Guards the value with a callback that carries a name.
+///
+/// The name shows up in the messages about a panicking callback
+/// and in [`current_guard_name`](fn.current_guard_name.html) while the callback runs.
+///
+/// ```
+/// use drop_guard::{current_guard_name, guard_named};
+///
+/// let _g = guard_named("db-rollback", vec![1, 2], |rows| {
+///     assert_eq!(Some("db-rollback"), current_guard_name());
+///     println!("{}: rolling back {:?}", current_guard_name().unwrap(), rows);
+/// });
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_named<T, F: FnOnce(T)>(
+    name: &'static str,
+    data: T,
+    func: F,
+) -> DropGuard<T, impl FnOnce(T)> {
+    let location = Location::caller();
+    events::enable(events::NAMED);
+    events::created::<T>(Some(name), location);
+    DropGuard::assemble(
+        data,
+        move |data| scoped(name, || func(data)),
+        true,
+        location,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn nested_names() {
+        let mut seen = Vec::new();
+        {
+            let _g = guard_named("outer", (), |_| {
+                seen.push(current_guard_name());
+                drop(guard_named("inner", (), |_| {}));
+                seen.push(current_guard_name());
+            });
+        }
+        assert_eq!(vec![Some("outer"), Some("outer")], seen);
+        assert_eq!(None, current_guard_name());
+    }
+
+    #[test]
+    fn remembers_failed() {
+        reset_failed();
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            scoped("broken", || panic!("cleanup failed"));
+        }));
+        assert!(r.is_err());
+        let location = Location::caller();
+        assert_eq!(
+            format!("DropGuard 'broken' created at {}", location),
+            failed(location).to_string()
+        );
+        assert_eq!(None, current_guard_name());
+    }
+}
@@ -0,0 +1,187 @@
+//! Hand the details of the current panic to callbacks.
+
+use crate::{guard, unwind, DropGuard};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic;
+use std::sync::Once;
+use std::thread;
+
+/// The message and location of a panic, see [`guard_with_panic_info`](fn.guard_with_panic_info.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicDetails {
+    message: String,
+    location: Option<(String, u32, u32)>,
+}
+
+impl PanicDetails {
+    /// The panic message, or `Box<dyn Any>` if the payload is no string.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The file where the panic happened.
+    pub fn file(&self) -> Option<&str> {
+        self.location.as_ref().map(|(file, _, _)| file.as_str())
+    }
+
+    /// The line where the panic happened.
+    pub fn line(&self) -> Option<u32> {
+        self.location.as_ref().map(|(_, line, _)| *line)
+    }
+
+    /// The column where the panic happened.
+    pub fn column(&self) -> Option<u32> {
+        self.location.as_ref().map(|(_, _, column)| *column)
+    }
+}
+
+impl fmt::Display for PanicDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some((file, line, column)) => {
+                write!(f, "'{}' at {}:{}:{}", self.message, file, line, column)
+            }
+            None => write!(f, "'{}'", self.message),
+        }
+    }
+}
+
+thread_local! {
+    /// The number of panics started on this thread, `resume_unwind` does not count.
+    static STARTED: Cell<u64> = const { Cell::new(0) };
+    /// The last panic started on this thread and its number.
+    static LAST: RefCell<Option<(u64, PanicDetails)>> = const { RefCell::new(None) };
+}
+
+/// Forgets the details once no panic is in flight, so they are not taken for a later one.
+fn forget_finished() {
+    if !thread::panicking() {
+        LAST.with(|last| last.borrow_mut().take());
+    }
+}
+
+/// The details of the panic that unwinds, if it started after panic number `seen`.
+///
+/// A panic resumed with `resume_unwind` records nothing, the details of an older
+/// panic are left out then.
+fn details_since(seen: u64) -> Option<PanicDetails> {
+    LAST.with(|last| match &*last.borrow() {
+        Some((number, details)) if *number > seen => Some(details.clone()),
+        _ => None,
+    })
+}
+
+static INSTALL: Once = Once::new();
+
+/// Installs a panic hook recording the details, the previous hook keeps running too.
+fn install_hook() {
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let details = PanicDetails {
+                message: unwind::message(info.payload()).to_string(),
+                location: info
+                    .location()
+                    .map(|l| (l.file().to_string(), l.line(), l.column())),
+            };
+            // the slots are gone if the thread is shutting down
+            let _ = STARTED.try_with(|started| {
+                let number = started.get() + 1;
+                started.set(number);
+                let _ = LAST.try_with(|last| *last.borrow_mut() = Some((number, details)));
+            });
+            previous(info);
+        }));
+    });
+}
+
+/// Guards the value with a callback that receives the details of the panic that unwinds, if any.
+///
+/// The first call installs a panic hook that records the details and then runs the
+/// previous hook. Replacing the hook afterwards with `std::panic::set_hook` stops the recording.
+/// An unwind started with `std::panic::resume_unwind` does not run the hook, so the
+/// callback receives `None` for it.
+///
+/// ```
+/// use drop_guard::guard_with_panic_info;
+/// use std::panic::catch_unwind;
+/// use std::sync::Mutex;
+///
+/// let report = Mutex::new(None);
+/// let result = catch_unwind(|| {
+///     let _g = guard_with_panic_info("tx", |_, panic| {
+///         *report.lock().unwrap() = panic.map(|p| p.message().to_string());
+///     });
+///     panic!("disk full");
+/// });
+/// assert!(result.is_err());
+/// assert_eq!(Some("disk full".to_string()), *report.lock().unwrap());
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_with_panic_info<T, F: FnOnce(T, Option<&PanicDetails>)>(
+    data: T,
+    func: F,
+) -> DropGuard<T, impl FnOnce(T)> {
+    install_hook();
+    forget_finished();
+    // a guard created while unwinding gets the details of the panic in flight
+    let seen = STARTED
+        .with(Cell::get)
+        .saturating_sub(thread::panicking() as u64);
+    guard(data, move |data| {
+        if thread::panicking() {
+            let details = details_since(seen);
+            func(data, details.as_ref())
+        } else {
+            forget_finished();
+            func(data, None)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::Mutex;
+
+    #[test]
+    fn no_details_without_panic() {
+        let mut seen = Some(None);
+        {
+            let _g = guard_with_panic_info(1, |_, p| seen = Some(p.cloned()));
+        }
+        assert_eq!(Some(None), seen);
+    }
+
+    #[test]
+    fn details_with_location() {
+        let seen = Mutex::new(None);
+        let r = catch_unwind(|| {
+            let _g = guard_with_panic_info(1, |_, p| *seen.lock().unwrap() = p.cloned());
+            panic!("boom {}", 42);
+        });
+        assert!(r.is_err());
+        let details = seen.lock().unwrap().take().unwrap();
+        assert_eq!("boom 42", details.message());
+        assert_eq!(Some(file!()), details.file());
+    }
+
+    #[test]
+    fn no_stale_details_for_resumed_unwinds() {
+        install_hook();
+        let r = catch_unwind(|| panic!("earlier"));
+        let payload = r.unwrap_err();
+
+        let seen = Mutex::new(Some(None));
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            let _g = guard_with_panic_info(1, |_, p| *seen.lock().unwrap() = Some(p.cloned()));
+            panic::resume_unwind(payload);
+        }));
+        assert!(r.is_err());
+        assert_eq!(Some(None), *seen.lock().unwrap());
+    }
+}
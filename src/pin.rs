@@ -0,0 +1,128 @@
+//! A guard for `!Unpin` values like futures.
+
+use std::fmt;
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Guards a value that may be pinned, the callback gets it as `Pin<&mut T>`.
+///
+/// A [`DropGuard`](struct.DropGuard.html) moves the value into its callback, which is not
+/// allowed once the value was pinned. This guard runs the callback on the value in place
+/// and drops it there afterwards, so the value is pinned structurally.
+///
+/// ```
+/// use drop_guard::pin_guard;
+/// use std::future::Future;
+/// use std::task::{Context, Poll, Waker};
+///
+/// let mut fut = Box::pin(pin_guard(async { 42 }, |_| println!("future dropped")));
+///
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert_eq!(Poll::Ready(42), fut.as_mut().as_pin_mut().poll(&mut cx));
+/// ```
+pub struct PinDropGuard<T, F: FnOnce(Pin<&mut T>)> {
+    data: T,
+    func: ManuallyDrop<F>,
+}
+
+/// Creates a [`PinDropGuard`](struct.PinDropGuard.html) from your data and a function.
+#[must_use]
+#[inline]
+pub fn pin_guard<T, F: FnOnce(Pin<&mut T>)>(data: T, func: F) -> PinDropGuard<T, F> {
+    PinDropGuard {
+        data,
+        func: ManuallyDrop::new(func),
+    }
+}
+
+impl<T, F: FnOnce(Pin<&mut T>)> PinDropGuard<T, F> {
+    /// Projects the pinned guard to the pinned value.
+    #[inline]
+    pub fn as_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        // SAFETY: `data` is pinned structurally: it is never moved out of the guard,
+        // the guard is only `Unpin` if `T` is and `Drop` only hands out `Pin<&mut T>`
+        unsafe { self.map_unchecked_mut(|g| &mut g.data) }
+    }
+
+    /// Projects the pinned guard to a shared reference of the value.
+    #[inline]
+    pub fn as_pin_ref(self: Pin<&Self>) -> Pin<&T> {
+        // SAFETY: see `as_pin_mut`
+        unsafe { self.map_unchecked(|g| &g.data) }
+    }
+}
+
+impl<T, F: FnOnce(Pin<&mut T>)> Deref for PinDropGuard<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+/// Only reachable while the guard is not pinned or if `T: Unpin`.
+impl<T, F: FnOnce(Pin<&mut T>)> DerefMut for PinDropGuard<T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+/// Await the guarded future in place, it may be `!Unpin`.
+impl<T: Future, F: FnOnce(Pin<&mut T>)> Future for PinDropGuard<T, F> {
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T::Output> {
+        self.as_pin_mut().poll(cx)
+    }
+}
+
+impl<T: fmt::Debug, F: FnOnce(Pin<&mut T>)> fmt::Debug for PinDropGuard<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinDropGuard")
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+impl<T, F: FnOnce(Pin<&mut T>)> Drop for PinDropGuard<T, F> {
+    fn drop(&mut self) {
+        // SAFETY: drop runs at most once and the callback is not touched afterwards
+        let func = unsafe { ManuallyDrop::take(&mut self.func) };
+        // SAFETY: the value stays in place and is dropped right after the callback
+        func(unsafe { Pin::new_unchecked(&mut self.data) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::marker::PhantomPinned;
+
+    #[test]
+    fn callback_sees_pinned_value() {
+        struct NotUnpin(u8, PhantomPinned);
+
+        let mut seen = 0;
+        {
+            let mut g = Box::pin(pin_guard(NotUnpin(1, PhantomPinned), |v| seen = v.0));
+            // SAFETY: the field is not structurally pinned
+            unsafe { g.as_mut().as_pin_mut().get_unchecked_mut().0 = 7 };
+        }
+        assert_eq!(7, seen);
+    }
+
+    #[test]
+    fn awaits_pinned_future() {
+        let mut dropped = false;
+        {
+            let fut = pin_guard(async { 5 }, |_| dropped = true);
+            let mut fut = std::pin::pin!(fut);
+            let mut cx = Context::from_waker(std::task::Waker::noop());
+            assert_eq!(Poll::Ready(5), fut.as_mut().poll(&mut cx));
+        }
+        assert!(dropped);
+    }
+}
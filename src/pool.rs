@@ -0,0 +1,200 @@
+//! Run independent cleanups on several threads.
+
+use crate::report::report;
+use crate::workers;
+use std::any::Any;
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+type Cleanup<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// The cleanups of a dropped pool, shared with the worker threads that help running them.
+struct Batch {
+    state: Mutex<BatchState>,
+    finished: Condvar,
+}
+
+struct BatchState {
+    cleanups: Vec<Cleanup<'static>>,
+    running: usize,
+    first_panic: Option<Box<dyn Any + Send>>,
+}
+
+impl Batch {
+    fn lock(&self) -> MutexGuard<'_, BatchState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs cleanups until none are left.
+    fn run(&self) {
+        let mut state = self.lock();
+        while let Some(cleanup) = state.cleanups.pop() {
+            state.running += 1;
+            drop(state);
+            let outcome = panic::catch_unwind(AssertUnwindSafe(cleanup));
+            state = self.lock();
+            state.running -= 1;
+            if let Err(payload) = outcome {
+                state.first_panic.get_or_insert(payload);
+            }
+        }
+        if state.running == 0 {
+            self.finished.notify_all();
+        }
+    }
+}
+
+/// Collects independent cleanups and runs them on a few threads on drop.
+///
+/// The dropping thread runs cleanups too and waits until all are done, the other threads
+/// are shared worker threads of the crate. The cleanups run in no particular order,
+/// so use it for compensations like I/O that do not depend on each other.
+/// [`TransactionScope::run_parallel`](struct.TransactionScope.html#method.run_parallel)
+/// uses it for the rollbacks of a transaction.
+/// If cleanups panic, the other cleanups still run and the first panic is passed on afterwards.
+///
+/// ```
+/// use drop_guard::CleanupPool;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// let deleted = AtomicUsize::new(0);
+/// {
+///     let mut cleanups = CleanupPool::new(4);
+///     for _bucket in 0..16 {
+///         cleanups.push(|| {
+///             // delete the uploaded object of this bucket ...
+///             deleted.fetch_add(1, Ordering::SeqCst);
+///         });
+///     }
+/// }
+/// assert_eq!(16, deleted.load(Ordering::SeqCst));
+/// ```
+pub struct CleanupPool<'a> {
+    threads: usize,
+    cleanups: Vec<Cleanup<'a>>,
+}
+
+impl<'a> CleanupPool<'a> {
+    /// Creates an empty pool that runs the cleanups on up to `threads` threads, the dropping one included.
+    pub fn new(threads: usize) -> CleanupPool<'a> {
+        CleanupPool {
+            threads: threads.max(1),
+            cleanups: Vec::new(),
+        }
+    }
+
+    /// Adds a cleanup.
+    pub fn push<F: FnOnce() + Send + 'a>(&mut self, cleanup: F) {
+        self.cleanups.push(Box::new(cleanup))
+    }
+
+    /// The number of cleanups in the pool.
+    pub fn len(&self) -> usize {
+        self.cleanups.len()
+    }
+
+    /// Whether the pool holds no cleanups.
+    pub fn is_empty(&self) -> bool {
+        self.cleanups.is_empty()
+    }
+
+    /// Drops all cleanups without running them.
+    pub fn commit(mut self) {
+        self.cleanups.clear()
+    }
+}
+
+impl fmt::Debug for CleanupPool<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CleanupPool")
+            .field("threads", &self.threads)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Drop for CleanupPool<'_> {
+    fn drop(&mut self) {
+        if self.cleanups.is_empty() {
+            return;
+        }
+        let helpers = self.threads.min(self.cleanups.len()) - 1;
+        // SAFETY: only the lifetime is erased. The dropping thread runs cleanups until none
+        // are left and then waits for those taken by workers, so none outlives the borrows.
+        let cleanups = unsafe {
+            mem::transmute::<Vec<Cleanup<'_>>, Vec<Cleanup<'static>>>(mem::take(&mut self.cleanups))
+        };
+        let batch = Arc::new(Batch {
+            state: Mutex::new(BatchState {
+                cleanups,
+                running: 0,
+                first_panic: None,
+            }),
+            finished: Condvar::new(),
+        });
+        for _ in 0..helpers {
+            let batch = batch.clone();
+            workers::execute(move || batch.run());
+        }
+        batch.run();
+
+        let mut state = batch.lock();
+        while state.running > 0 {
+            state = batch
+                .finished
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        if let Some(payload) = state.first_panic.take() {
+            drop(state);
+            if thread::panicking() {
+                report(format_args!(
+                    "a cleanup of the CleanupPool panicked during unwinding: {}",
+                    crate::unwind::message(&*payload)
+                ));
+            } else {
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn commit_skips() {
+        let ran = AtomicUsize::new(0);
+        let mut pool = CleanupPool::new(2);
+        pool.push(|| {
+            ran.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(1, pool.len());
+        pool.commit();
+        assert_eq!(0, ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panic_after_all_ran() {
+        let ran = AtomicUsize::new(0);
+        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut pool = CleanupPool::new(2);
+            for i in 0..8 {
+                let ran = &ran;
+                pool.push(move || {
+                    if i == 3 {
+                        panic!("compensation failed");
+                    }
+                    ran.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        }));
+        assert!(r.is_err());
+        assert_eq!(7, ran.load(Ordering::SeqCst));
+    }
+}
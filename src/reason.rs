@@ -0,0 +1,99 @@
+//! Tell the callback why it runs.
+
+use crate::{events, guard, DropGuard};
+use std::cell::Cell;
+
+/// Why the callback of a guard runs, see [`guard_with_reason`](fn.guard_with_reason.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The guard went out of scope or was dropped normally.
+    ScopeExit,
+    /// The guard was dropped while a panic unwinds.
+    Panic,
+    /// The callback was run with [`DropGuard::trigger`](struct.DropGuard.html#method.trigger).
+    Explicit,
+}
+
+thread_local! {
+    /// The reason of the callback that is currently running on this thread.
+    static CURRENT: Cell<DropReason> = const { Cell::new(DropReason::ScopeExit) };
+}
+
+/// Restores the reason of an outer callback, even if the inner one panics.
+struct Restore(DropReason);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|c| c.set(self.0));
+    }
+}
+
+/// Runs `f` with `reason` available to callbacks created by `guard_with_reason`.
+#[inline]
+pub(crate) fn scoped<R>(reason: DropReason, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore(CURRENT.with(|c| c.replace(reason)));
+    f()
+}
+
+/// Guards the value with a callback that is told why it runs.
+///
+/// ```
+/// use drop_guard::{guard_with_reason, DropReason};
+/// use std::cell::RefCell;
+///
+/// let reasons = RefCell::new(Vec::new());
+/// {
+///     let _g = guard_with_reason(1, |_, reason| reasons.borrow_mut().push(reason));
+///     guard_with_reason(2, |_, reason| reasons.borrow_mut().push(reason)).trigger();
+/// }
+/// assert_eq!(vec![DropReason::Explicit, DropReason::ScopeExit], *reasons.borrow());
+/// ```
+///
+/// The reason is only known while the guard runs the callback, calling the
+/// callback from [`into_parts`](struct.DropGuard.html#method.into_parts) yourself
+/// reports the reason of the surrounding callback or `ScopeExit`.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_with_reason<T, F: FnOnce(T, DropReason)>(
+    data: T,
+    func: F,
+) -> DropGuard<T, impl FnOnce(T)> {
+    events::enable(events::REASONS);
+    guard(data, move |data| func(data, CURRENT.with(Cell::get)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::catch_unwind;
+    use std::sync::Mutex;
+
+    #[test]
+    fn panic_reason() {
+        let reasons = Mutex::new(Vec::new());
+        let r = catch_unwind(|| {
+            let _g = guard_with_reason(1, |_, reason| reasons.lock().unwrap().push(reason));
+            panic!("unwinding");
+        });
+        assert!(r.is_err());
+        assert_eq!(vec![DropReason::Panic], *reasons.lock().unwrap());
+    }
+
+    #[test]
+    fn nested_reasons() {
+        let reasons = Mutex::new(Vec::new());
+        guard_with_reason(1, |_, reason| {
+            // a guard dropped inside the callback has its own reason
+            drop(guard_with_reason(2, |_, r| reasons.lock().unwrap().push(r)));
+            reasons.lock().unwrap().push(reason);
+            assert_eq!(DropReason::Explicit, CURRENT.with(Cell::get));
+        })
+        .trigger();
+        assert_eq!(
+            vec![DropReason::ScopeExit, DropReason::Explicit],
+            *reasons.lock().unwrap()
+        );
+        assert_eq!(DropReason::ScopeExit, CURRENT.with(Cell::get));
+    }
+}
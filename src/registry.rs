@@ -0,0 +1,162 @@
+//! Guards that still run if a panic aborts the process.
+
+use crate::{guard, DropGuard};
+use std::fmt;
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once, TryLockError};
+
+type Entry = (u64, Box<dyn FnOnce(bool) + Send>);
+
+static REGISTRY: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static INSTALL: Once = Once::new();
+
+/// Locks `mutex`, or gives up instead of waiting if `wait` is not set.
+fn lock<T>(mutex: &Mutex<T>, wait: bool) -> Option<MutexGuard<'_, T>> {
+    if wait {
+        return Some(mutex.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// A guard that is also listed in a global registry, see [`guard_registered`](fn.guard_registered.html).
+pub struct RegisteredGuard<T, F: FnOnce(T)> {
+    id: u64,
+    slot: Arc<Mutex<Option<DropGuard<T, F>>>>,
+}
+
+/// Guards the value and registers the guard, so its callback can run without unwinding.
+///
+/// With `panic = "abort"` destructors never run. After [`install_abort_hook`](fn.install_abort_hook.html)
+/// the panic hook runs the callbacks of all registered guards before the process aborts.
+/// [`flush_registered`](fn.flush_registered.html) runs them by hand, e.g. before `std::process::exit`.
+/// A normal drop runs the callback and removes the guard from the registry.
+///
+/// ```
+/// use drop_guard::{guard_registered, install_abort_hook};
+///
+/// install_abort_hook();
+/// let pid_file = guard_registered("/tmp/example.pid", |path| {
+///     let _ = std::fs::remove_file(path);
+/// });
+/// assert_eq!(Some("/tmp/example.pid"), pid_file.with(|path| *path));
+/// ```
+#[must_use]
+#[track_caller]
+pub fn guard_registered<T, F>(data: T, func: F) -> RegisteredGuard<T, F>
+where
+    T: Send + 'static,
+    F: FnOnce(T) + Send + 'static,
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let slot = Arc::new(Mutex::new(Some(guard(data, func))));
+    let weak = Arc::downgrade(&slot);
+    let entry = Box::new(move |wait| {
+        if let Some(slot) = weak.upgrade() {
+            // a guard busy in `with` on the panicking thread is skipped
+            if let Some(mut slot) = lock(&slot, wait) {
+                drop(slot.take());
+            }
+        }
+    });
+    lock(&REGISTRY, true).unwrap().push((id, entry));
+    RegisteredGuard { id, slot }
+}
+
+/// Runs the callbacks of all registered guards, the latest first.
+pub fn flush_registered() {
+    flush(true)
+}
+
+fn flush(wait: bool) {
+    let entries = match lock(&REGISTRY, wait) {
+        Some(mut registry) => std::mem::take(&mut *registry),
+        None => return,
+    };
+    for (_, entry) in entries.into_iter().rev() {
+        entry(wait);
+    }
+}
+
+/// Installs a panic hook that runs the registered guards if the panic aborts.
+///
+/// It only flushes when the crate is built with `panic = "abort"`, otherwise the guards
+/// run during unwinding anyway. The previous hook runs afterwards.
+pub fn install_abort_hook() {
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if cfg!(panic = "abort") {
+                flush(false);
+            }
+            previous(info);
+        }));
+    });
+}
+
+impl<T, F: FnOnce(T)> RegisteredGuard<T, F> {
+    /// Calls `f` with the value, or returns `None` if the registry was flushed already.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut slot = lock(&self.slot, true).unwrap();
+        slot.as_mut().map(|g| f(g))
+    }
+}
+
+impl<T: fmt::Debug, F: FnOnce(T)> fmt::Debug for RegisteredGuard<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slot = lock(&self.slot, true).unwrap();
+        f.debug_struct("RegisteredGuard")
+            .field("data", &slot.as_deref())
+            .finish()
+    }
+}
+
+impl<T, F: FnOnce(T)> Drop for RegisteredGuard<T, F> {
+    fn drop(&mut self) {
+        lock(&REGISTRY, true)
+            .unwrap()
+            .retain(|(id, _)| *id != self.id);
+        let guard = lock(&self.slot, true).unwrap().take();
+        drop(guard);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn drop_unregisters() {
+        let count = Arc::new(AtomicUsize::new(0));
+        {
+            let count = count.clone();
+            let g = guard_registered(1, move |_| {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(Some(2), g.with(|v| *v + 1));
+        }
+        assert_eq!(1, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn flush_runs_once() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let g = {
+            let count = count.clone();
+            guard_registered(1, move |_| {
+                count.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        flush_registered();
+        assert_eq!(1, count.load(Ordering::SeqCst));
+        assert_eq!(None, g.with(|v| *v));
+        drop(g);
+        assert_eq!(1, count.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,52 @@
+//! Where the diagnostics of the crate go.
+
+use std::fmt;
+use std::sync::RwLock;
+
+/// Receives the diagnostics the crate would otherwise print to stderr, e.g. to forward them to a logger.
+///
+/// The handlers for specific events, like a [`DeadlineHandler`](type.DeadlineHandler.html),
+/// take precedence. Whatever reaches the default of such a handler ends up here.
+pub type ReportHandler = fn(fmt::Arguments<'_>);
+
+static HANDLER: Hook<ReportHandler> = Hook::new();
+
+/// Routes all diagnostics of the crate to `handler` instead of stderr.
+///
+/// ```
+/// use drop_guard::{guard, set_report_handler, PanicPolicy};
+///
+/// set_report_handler(|message| println!("[drop_guard] {}", message));
+/// {
+///     let _g = guard(1, |_| panic!("cleanup failed"))
+///         .with_panic_policy(PanicPolicy::LogAndContinue);
+/// }
+/// ```
+pub fn set_report_handler(handler: ReportHandler) {
+    HANDLER.set(handler)
+}
+
+/// Hands a diagnostic to the [`ReportHandler`](type.ReportHandler.html), by default stderr.
+pub(crate) fn report(message: fmt::Arguments<'_>) {
+    match HANDLER.get() {
+        Some(handler) => handler(message),
+        None => eprintln!("{}", message),
+    }
+}
+
+/// A handler that can be installed at runtime, unset by default.
+pub(crate) struct Hook<H>(RwLock<Option<H>>);
+
+impl<H: Copy> Hook<H> {
+    pub(crate) const fn new() -> Self {
+        Hook(RwLock::new(None))
+    }
+
+    pub(crate) fn set(&self, handler: H) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Some(handler);
+    }
+
+    pub(crate) fn get(&self) -> Option<H> {
+        *self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,76 @@
+//! Retrieve what the callback returned after the guard was dropped.
+
+use crate::{guard, DropGuard};
+use std::sync::mpsc::{channel, Receiver};
+
+/// Receives the return value of a callback created with [`guard_with_result`](fn.guard_with_result.html).
+#[derive(Debug)]
+pub struct ResultHandle<R> {
+    receiver: Receiver<R>,
+}
+
+/// Guards the value with a callback whose return value can be picked up with the [`ResultHandle`](struct.ResultHandle.html).
+///
+/// ```
+/// use drop_guard::guard_with_result;
+/// use std::io::Write;
+///
+/// let (mut out, flushed) = guard_with_result(Vec::new(), |buf| buf.len());
+/// write!(out, "12345").unwrap();
+/// assert_eq!(None, flushed.try_take());
+///
+/// drop(out);
+/// assert_eq!(Some(5), flushed.try_take());
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_with_result<T, R, F: FnOnce(T) -> R>(
+    data: T,
+    func: F,
+) -> (DropGuard<T, impl FnOnce(T)>, ResultHandle<R>) {
+    let (sender, receiver) = channel();
+    let g = guard(data, move |data| {
+        // nobody is interested if the handle is gone already
+        let _ = sender.send(func(data));
+    });
+    (g, ResultHandle { receiver })
+}
+
+impl<R> ResultHandle<R> {
+    /// Returns the result if the callback has run, without blocking.
+    #[inline]
+    pub fn try_take(&self) -> Option<R> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Blocks until the guard is dropped.
+    ///
+    /// Returns `None` if the guard was dropped without running the callback,
+    /// e.g. because it was disarmed or cancelled.
+    ///
+    /// ```
+    /// use drop_guard::guard_with_result;
+    /// use std::thread;
+    ///
+    /// let (worker, result) = guard_with_result(vec![1, 2, 3], |v| v.iter().sum::<i32>());
+    /// thread::spawn(move || drop(worker));
+    /// assert_eq!(Some(6), result.wait());
+    /// ```
+    #[inline]
+    pub fn wait(self) -> Option<R> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelled_yields_none() {
+        let (g, result) = guard_with_result(1, |i| i + 1);
+        g.cancel();
+        assert_eq!(None, result.wait());
+    }
+}
@@ -0,0 +1,169 @@
+//! Retry failing callbacks with a backoff.
+
+use crate::{guard, DropGuard};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait between two attempts of a [`Retry`](struct.Retry.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Backoff {
+    /// Waits the same time before every retry.
+    Fixed(Duration),
+    /// Starts with `initial` and doubles the wait before every retry, up to `max`.
+    Exponential {
+        /// The wait before the first retry.
+        initial: Duration,
+        /// The longest wait.
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(2u32.saturating_pow(retry))
+                .map_or(max, |d| d.min(max)),
+        }
+    }
+}
+
+/// Runs a fallible action up to `max_attempts` times, waiting by the [`Backoff`](enum.Backoff.html) in between.
+///
+/// ```
+/// use drop_guard::{ErrorCollector, Retry};
+/// use std::time::Duration;
+///
+/// let retry = Retry::new(3).fixed(Duration::from_millis(1));
+/// let errors = ErrorCollector::new();
+/// {
+///     let mut calls = 0;
+///     let _lock = errors.guard("lock-17", move |name| {
+///         retry.run(|| {
+///             calls += 1;
+///             if calls < 3 { Err(format!("{} busy", name)) } else { Ok(()) }
+///         })
+///     });
+/// }
+/// assert!(errors.is_empty());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Retry {
+    max_attempts: u32,
+    backoff: Backoff,
+}
+
+impl Retry {
+    /// Tries at most `max_attempts` times without waiting, but at least once.
+    pub fn new(max_attempts: u32) -> Retry {
+        Retry {
+            max_attempts: max_attempts.max(1),
+            backoff: Backoff::Fixed(Duration::ZERO),
+        }
+    }
+
+    /// Waits `delay` before every retry.
+    #[must_use]
+    pub fn fixed(self, delay: Duration) -> Retry {
+        self.backoff(Backoff::Fixed(delay))
+    }
+
+    /// Waits `initial` before the first retry and doubles it up to `max`.
+    #[must_use]
+    pub fn exponential(self, initial: Duration, max: Duration) -> Retry {
+        self.backoff(Backoff::Exponential { initial, max })
+    }
+
+    /// Waits by `backoff` between the attempts.
+    #[must_use]
+    pub fn backoff(self, backoff: Backoff) -> Retry {
+        Retry { backoff, ..self }
+    }
+
+    /// Calls `action` until it succeeds or all attempts are used up, then returns the last error.
+    pub fn run<R, E, A: FnMut() -> Result<R, E>>(&self, mut action: A) -> Result<R, E> {
+        let mut retry = 0;
+        loop {
+            match action() {
+                Err(_) if retry + 1 < self.max_attempts => {
+                    thread::sleep(self.backoff.delay(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Guards the value with a callback that is retried on errors, `give_up` gets the last error.
+///
+/// ```
+/// use drop_guard::{guard_with_retry, Retry};
+///
+/// let mut reported = None;
+/// {
+///     let _g = guard_with_retry(
+///         "api-session",
+///         Retry::new(2),
+///         |_| Err("timeout"),
+///         |session, error| reported = Some(format!("{}: {}", session, error)),
+///     );
+/// }
+/// assert_eq!(Some("api-session: timeout".to_string()), reported);
+/// ```
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn guard_with_retry<T, E, A, G>(
+    data: T,
+    retry: Retry,
+    mut attempt: A,
+    give_up: G,
+) -> DropGuard<T, impl FnOnce(T)>
+where
+    A: FnMut(&mut T) -> Result<(), E>,
+    G: FnOnce(T, E),
+{
+    guard(data, move |mut data| {
+        if let Err(error) = retry.run(|| attempt(&mut data)) {
+            give_up(data, error)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_delays() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+        };
+        let delays: Vec<_> = (0..4).map(|r| backoff.delay(r).as_millis()).collect();
+        assert_eq!(vec![10, 20, 40, 50], delays);
+        assert_eq!(Duration::from_millis(50), backoff.delay(u32::MAX));
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let mut calls = 0;
+        let result: Result<(), u32> = Retry::new(4).run(|| {
+            calls += 1;
+            Err(calls)
+        });
+        assert_eq!(Err(4), result);
+    }
+
+    #[test]
+    fn at_least_one_attempt() {
+        let mut calls = 0;
+        let _ = Retry::new(0).run(|| {
+            calls += 1;
+            Err::<(), ()>(())
+        });
+        assert_eq!(1, calls);
+    }
+}
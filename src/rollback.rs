@@ -0,0 +1,60 @@
+//! Types that know how to undo themselves.
+
+/// Undoes the change a value stands for, e.g. with [`GuardStack::push_item`](struct.GuardStack.html#method.push_item).
+///
+/// Every `FnOnce()` closure is a `Rollback` too.
+///
+/// ```
+/// use drop_guard::{GuardStack, Rollback};
+/// use std::cell::RefCell;
+///
+/// struct CreatedDir<'a> {
+///     path: &'static str,
+///     removed: &'a RefCell<Vec<&'static str>>,
+/// }
+///
+/// impl Rollback for CreatedDir<'_> {
+///     fn rollback(self) {
+///         self.removed.borrow_mut().push(self.path);
+///     }
+/// }
+///
+/// let removed = RefCell::new(Vec::new());
+/// {
+///     let mut undo = GuardStack::new();
+///     undo.push_item(CreatedDir { path: "/srv/a", removed: &removed });
+///     undo.push_item(CreatedDir { path: "/srv/b", removed: &removed });
+/// }
+/// assert_eq!(vec!["/srv/b", "/srv/a"], *removed.borrow());
+/// ```
+pub trait Rollback {
+    /// Undoes the change.
+    fn rollback(self);
+}
+
+impl<F: FnOnce()> Rollback for F {
+    fn rollback(self) {
+        self()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter<'a>(&'a mut u32);
+
+    impl Rollback for Counter<'_> {
+        fn rollback(self) {
+            *self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn types_and_closures() {
+        let mut count = 0;
+        Counter(&mut count).rollback();
+        (|| count += 10).rollback();
+        assert_eq!(11, count);
+    }
+}
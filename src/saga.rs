@@ -0,0 +1,179 @@
+//! Steps with compensations that undo the completed steps on failure.
+
+use crate::GuardStack;
+use std::error::Error;
+use std::fmt;
+
+type Action<'a, E> = Box<dyn FnOnce() -> Result<(), E> + 'a>;
+
+struct Step<'a, E> {
+    name: &'static str,
+    action: Action<'a, E>,
+    compensation: Box<dyn FnOnce() + 'a>,
+}
+
+/// Runs named steps in order and compensates the completed ones in reverse order on the first failure.
+///
+/// ```
+/// use drop_guard::Saga;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// let result = Saga::new()
+///     .step(
+///         "reserve seat",
+///         || {
+///             log.borrow_mut().push("reserved");
+///             Ok(())
+///         },
+///         || log.borrow_mut().push("released"),
+///     )
+///     .step(
+///         "charge card",
+///         || Err("card declined"),
+///         || log.borrow_mut().push("refunded"),
+///     )
+///     .run();
+///
+/// let error = result.unwrap_err();
+/// assert_eq!("charge card", error.step());
+/// assert_eq!(&"card declined", error.error());
+/// assert_eq!(vec!["reserved", "released"], *log.borrow());
+/// ```
+pub struct Saga<'a, E> {
+    steps: Vec<Step<'a, E>>,
+}
+
+impl<'a, E> Saga<'a, E> {
+    /// Creates a saga without steps.
+    pub fn new() -> Saga<'a, E> {
+        Saga { steps: Vec::new() }
+    }
+
+    /// Adds a step, `compensation` runs if a later step fails.
+    #[must_use]
+    pub fn step<A, C>(mut self, name: &'static str, action: A, compensation: C) -> Saga<'a, E>
+    where
+        A: FnOnce() -> Result<(), E> + 'a,
+        C: FnOnce() + 'a,
+    {
+        self.steps.push(Step {
+            name,
+            action: Box::new(action),
+            compensation: Box::new(compensation),
+        });
+        self
+    }
+
+    /// Runs the actions in order.
+    ///
+    /// On the first error or panic the compensations of the completed steps run in reverse order.
+    pub fn run(self) -> Result<(), SagaError<E>> {
+        let mut completed = GuardStack::new();
+        for step in self.steps {
+            if let Err(error) = (step.action)() {
+                drop(completed);
+                return Err(SagaError {
+                    step: step.name,
+                    error,
+                });
+            }
+            completed.push(step.compensation);
+        }
+        completed.commit();
+        Ok(())
+    }
+}
+
+impl<E> Default for Saga<'_, E> {
+    fn default() -> Self {
+        Saga::new()
+    }
+}
+
+impl<E> fmt::Debug for Saga<'_, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.steps.iter().map(|s| s.name).collect();
+        f.debug_struct("Saga").field("steps", &names).finish()
+    }
+}
+
+/// The step of a [`Saga`](struct.Saga.html) that failed and its error.
+#[derive(Debug)]
+pub struct SagaError<E> {
+    step: &'static str,
+    error: E,
+}
+
+impl<E> SagaError<E> {
+    /// The name of the failed step.
+    pub fn step(&self) -> &'static str {
+        self.step
+    }
+
+    /// The error of the failed step.
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Takes the error of the failed step.
+    pub fn into_error(self) -> E {
+        self.error
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for SagaError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step '{}' failed: {}", self.step, self.error)
+    }
+}
+
+impl<E: Error + 'static> Error for SagaError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn success_keeps_everything() {
+        let log = RefCell::new(Vec::new());
+        let result: Result<(), SagaError<()>> = Saga::new()
+            .step(
+                "a",
+                || {
+                    log.borrow_mut().push("a");
+                    Ok(())
+                },
+                || log.borrow_mut().push("undo a"),
+            )
+            .step(
+                "b",
+                || {
+                    log.borrow_mut().push("b");
+                    Ok(())
+                },
+                || log.borrow_mut().push("undo b"),
+            )
+            .run();
+        assert!(result.is_ok());
+        assert_eq!(vec!["a", "b"], *log.borrow());
+    }
+
+    #[test]
+    fn failed_step_is_not_compensated() {
+        let log = RefCell::new(Vec::new());
+        let error = Saga::new()
+            .step("a", || Ok(()), || log.borrow_mut().push("undo a"))
+            .step("b", || Ok(()), || log.borrow_mut().push("undo b"))
+            .step("c", || Err(3), || log.borrow_mut().push("undo c"))
+            .run()
+            .unwrap_err();
+        assert_eq!("step 'c' failed: 3", error.to_string());
+        assert_eq!(vec!["undo b", "undo a"], *log.borrow());
+    }
+}
@@ -0,0 +1,84 @@
+//! Optional `serde` support, enabled with the `serde` feature.
+
+use crate::{guard, DropGuard, Strategy};
+use serde::de::{Deserialize, DeserializeSeed, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::marker::PhantomData;
+use std::panic::Location;
+
+/// Serialize the captured value, the guard itself is transparent.
+///
+/// ```
+/// use drop_guard::guard;
+///
+/// let config = guard(vec![1, 2, 3], |_| {});
+/// assert_eq!("[1,2,3]", serde_json::to_string(&config).unwrap());
+/// ```
+impl<T: Serialize, F: FnOnce(T), S: Strategy> Serialize for DropGuard<T, F, S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+/// Deserializes a `T` and guards it with the callback.
+///
+/// ```
+/// use drop_guard::GuardSeed;
+/// use serde::de::DeserializeSeed;
+///
+/// let mut de = serde_json::Deserializer::from_str("[1, 2, 3]");
+/// let config = GuardSeed::new(|c: Vec<u8>| println!("writing back {:?}", c))
+///     .deserialize(&mut de)
+///     .unwrap();
+/// assert_eq!(vec![1, 2, 3], *config);
+/// ```
+pub struct GuardSeed<T, F: FnOnce(T)> {
+    func: F,
+    location: &'static Location<'static>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, F: FnOnce(T)> GuardSeed<T, F> {
+    /// Prepares the callback for the value to be deserialized.
+    #[must_use]
+    #[inline]
+    #[track_caller]
+    pub fn new(func: F) -> GuardSeed<T, F> {
+        GuardSeed {
+            func,
+            location: Location::caller(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>, F: FnOnce(T)> DeserializeSeed<'de> for GuardSeed<T, F> {
+    type Value = DropGuard<T, F>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut g = guard(T::deserialize(deserializer)?, self.func);
+        g.location = self.location;
+        Ok(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn round_trip_keeps_callback() {
+        let mut written: Option<BTreeMap<String, u8>> = None;
+        {
+            let mut de = serde_json::Deserializer::from_str(r#"{"a":1}"#);
+            let config = GuardSeed::new(|c| written = Some(c))
+                .deserialize(&mut de)
+                .unwrap();
+            let line = line!() - 3;
+            assert_eq!(line, config.created_at().line());
+            assert_eq!(r#"{"a":1}"#, serde_json::to_string(&config).unwrap());
+        }
+        assert_eq!(Some(&1), written.unwrap().get("a"));
+    }
+}
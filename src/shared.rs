@@ -0,0 +1,120 @@
+//! A guard that can be cloned and fires when the last clone is dropped.
+
+use crate::{Always, DropGuard, Strategy};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Shares the guarded value between clones, the callback runs once the last clone is dropped.
+///
+/// The value is shared, so it can only be accessed immutably.
+///
+/// ```
+/// use drop_guard::shared_guard;
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let done = Arc::new(AtomicBool::new(false));
+/// let token = {
+///     let done = done.clone();
+///     shared_guard("keep-alive", move |_| done.store(true, Ordering::SeqCst))
+/// };
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let token = token.clone();
+///         thread::spawn(move || assert_eq!("keep-alive", *token))
+///     })
+///     .collect();
+/// drop(token);
+///
+/// for w in workers {
+///     w.join().unwrap();
+/// }
+/// assert!(done.load(Ordering::SeqCst));
+/// ```
+pub struct SharedGuard<T, F: FnOnce(T), S: Strategy = Always> {
+    inner: Arc<DropGuard<T, F, S>>,
+}
+
+/// Creates a [`SharedGuard`](struct.SharedGuard.html) from your data and a function.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn shared_guard<T, F: FnOnce(T)>(data: T, func: F) -> SharedGuard<T, F> {
+    crate::guard(data, func).into()
+}
+
+impl<T, F: FnOnce(T), S: Strategy> SharedGuard<T, F, S> {
+    /// The number of clones sharing this guard, including this one.
+    ///
+    /// ```
+    /// use drop_guard::shared_guard;
+    ///
+    /// let a = shared_guard(0, |_| {});
+    /// let b = a.clone();
+    /// assert_eq!(2, a.count());
+    /// drop(b);
+    /// assert_eq!(1, a.count());
+    /// ```
+    #[inline]
+    pub fn count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+}
+
+/// Share an existing guard.
+impl<T, F: FnOnce(T), S: Strategy> From<DropGuard<T, F, S>> for SharedGuard<T, F, S> {
+    fn from(guard: DropGuard<T, F, S>) -> Self {
+        SharedGuard {
+            inner: Arc::new(guard),
+        }
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> Clone for SharedGuard<T, F, S> {
+    fn clone(&self) -> Self {
+        SharedGuard {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> Deref for SharedGuard<T, F, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: fmt::Debug, F: FnOnce(T), S: Strategy> fmt::Debug for SharedGuard<T, F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedGuard")
+            .field("data", &**self)
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn fires_once_after_last_clone() {
+        let calls = AtomicUsize::new(0);
+        let a = shared_guard(1, |_| {
+            calls.fetch_add(1, Ordering::Relaxed);
+        });
+        let b = a.clone();
+        let c = b.clone();
+        drop(a);
+        drop(c);
+        assert_eq!(0, calls.load(Ordering::Relaxed));
+        drop(b);
+        assert_eq!(1, calls.load(Ordering::Relaxed));
+    }
+}
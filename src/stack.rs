@@ -0,0 +1,198 @@
+//! A stack of cleanups that run in reverse order.
+
+use crate::{guard, DropGuard, Rollback};
+use std::fmt;
+
+type Cleanup<'a> = DropGuard<(), Box<dyn FnOnce(()) + 'a>>;
+
+/// Collects cleanups step by step and runs them in reverse order on drop.
+///
+/// Push the undo of every step once the step succeeded. If a later step fails,
+/// dropping the stack undoes the steps done so far, [`commit`](#method.commit) keeps them.
+///
+/// ```
+/// use drop_guard::GuardStack;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// let setup = || -> Result<(), &str> {
+///     let mut undo = GuardStack::new();
+///     log.borrow_mut().push("mkdir");
+///     undo.push(|| log.borrow_mut().push("rmdir"));
+///     log.borrow_mut().push("write");
+///     undo.push(|| log.borrow_mut().push("unlink"));
+///     Err("chmod failed")?;
+///     undo.commit();
+///     Ok(())
+/// };
+/// assert!(setup().is_err());
+/// assert_eq!(vec!["mkdir", "write", "unlink", "rmdir"], *log.borrow());
+/// ```
+#[derive(Default)]
+pub struct GuardStack<'a> {
+    cleanups: Vec<Cleanup<'a>>,
+}
+
+impl<'a> GuardStack<'a> {
+    /// Creates an empty stack.
+    pub fn new() -> GuardStack<'a> {
+        GuardStack::default()
+    }
+
+    /// Adds a cleanup that runs before all cleanups pushed earlier.
+    #[track_caller]
+    pub fn push<F: FnOnce() + 'a>(&mut self, cleanup: F) {
+        self.cleanups.push(guard((), Box::new(move |()| cleanup())));
+    }
+
+    /// Moves the cleanups of `other` on top of this stack, they run before the ones already here.
+    pub fn append(&mut self, other: &mut GuardStack<'a>) {
+        self.cleanups.append(&mut other.cleanups)
+    }
+
+    /// Adds a value that is rolled back before all cleanups pushed earlier.
+    #[track_caller]
+    pub fn push_item<R: Rollback + 'a>(&mut self, item: R) {
+        self.push(move || item.rollback())
+    }
+
+    /// The number of cleanups on the stack.
+    pub fn len(&self) -> usize {
+        self.cleanups.len()
+    }
+
+    /// Whether there are no cleanups on the stack.
+    pub fn is_empty(&self) -> bool {
+        self.cleanups.is_empty()
+    }
+
+    /// Drops all cleanups without running them, the stack can be used again.
+    pub fn dismiss_all(&mut self) {
+        for cleanup in self.cleanups.drain(..) {
+            cleanup.cancel();
+        }
+    }
+
+    /// Marks the current top of the stack to return to with
+    /// [`rollback_to`](#method.rollback_to) or [`commit_to`](#method.commit_to).
+    ///
+    /// ```
+    /// use drop_guard::GuardStack;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let mut stack = GuardStack::new();
+    /// stack.push(|| log.borrow_mut().push("undo validated step"));
+    ///
+    /// let nested = stack.checkpoint();
+    /// stack.push(|| log.borrow_mut().push("undo nested 1"));
+    /// stack.push(|| log.borrow_mut().push("undo nested 2"));
+    /// stack.rollback_to(nested);
+    /// assert_eq!(vec!["undo nested 2", "undo nested 1"], *log.borrow());
+    /// assert_eq!(1, stack.len());
+    /// # stack.commit();
+    /// ```
+    pub fn checkpoint(&self) -> Savepoint {
+        Savepoint {
+            depth: self.cleanups.len(),
+        }
+    }
+
+    /// Runs the cleanups pushed after `savepoint` in reverse order.
+    ///
+    /// Nothing happens if the stack is already below the savepoint.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) {
+        let mut above = self.split_off(savepoint);
+        above.reverse();
+    }
+
+    /// Drops the cleanups pushed after `savepoint` without running them.
+    pub fn commit_to(&mut self, savepoint: Savepoint) {
+        for cleanup in self.split_off(savepoint) {
+            cleanup.cancel();
+        }
+    }
+
+    fn split_off(&mut self, savepoint: Savepoint) -> Vec<Cleanup<'a>> {
+        let depth = savepoint.depth.min(self.cleanups.len());
+        self.cleanups.split_off(depth)
+    }
+
+    /// Keeps the changes of all steps, none of the cleanups run.
+    pub fn commit(mut self) {
+        self.dismiss_all()
+    }
+}
+
+/// A position on a [`GuardStack`](struct.GuardStack.html), see [`checkpoint`](struct.GuardStack.html#method.checkpoint).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Savepoint {
+    depth: usize,
+}
+
+impl fmt::Debug for GuardStack<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuardStack")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Drop for GuardStack<'_> {
+    fn drop(&mut self) {
+        // the vector drops front to back and keeps going if a cleanup panics
+        self.cleanups.reverse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn dismiss_and_reuse() {
+        let log = RefCell::new(Vec::new());
+        {
+            let mut stack = GuardStack::new();
+            stack.push(|| log.borrow_mut().push(1));
+            stack.dismiss_all();
+            assert!(stack.is_empty());
+            stack.push(|| log.borrow_mut().push(2));
+            assert_eq!(1, stack.len());
+        }
+        assert_eq!(vec![2], *log.borrow());
+    }
+
+    #[test]
+    fn panicking_cleanup_runs_the_rest() {
+        let log = RefCell::new(Vec::new());
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            let mut stack = GuardStack::new();
+            stack.push(|| log.borrow_mut().push(1));
+            stack.push(|| panic!("cleanup failed"));
+            stack.push(|| log.borrow_mut().push(3));
+        }));
+        assert!(r.is_err());
+        assert_eq!(vec![3, 1], *log.borrow());
+    }
+
+    #[test]
+    fn nested_savepoints() {
+        let log = RefCell::new(Vec::new());
+        let mut stack = GuardStack::new();
+        stack.push(|| log.borrow_mut().push(1));
+        let outer = stack.checkpoint();
+        stack.push(|| log.borrow_mut().push(2));
+        let inner = stack.checkpoint();
+        stack.push(|| log.borrow_mut().push(3));
+        stack.commit_to(inner);
+        assert_eq!(2, stack.len());
+        stack.rollback_to(outer);
+        stack.rollback_to(inner);
+        assert_eq!(vec![2], *log.borrow());
+        drop(stack);
+        assert_eq!(vec![2, 1], *log.borrow());
+    }
+}
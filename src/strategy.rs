@@ -0,0 +1,89 @@
+//! Strategies deciding whether the callback runs when a guard is dropped.
+
+use std::thread;
+
+/// Decides at drop time whether the callback of a [`DropGuard`](struct.DropGuard.html) runs.
+///
+/// Implement it to write your own strategy:
+///
+/// ```
+/// use drop_guard::{DropGuard, Strategy};
+///
+/// enum Never {}
+///
+/// impl Strategy for Never {
+///     fn should_run() -> bool {
+///         false
+///     }
+/// }
+///
+/// let _g = DropGuard::<_, _, Never>::with_strategy(1, |_| unreachable!());
+/// ```
+pub trait Strategy {
+    /// Returns `true` if the callback should run now.
+    fn should_run() -> bool;
+}
+
+/// Always run the callback, the default strategy.
+#[derive(Debug)]
+pub enum Always {}
+
+/// Only run the callback if the scope is left normally, see [`guard_on_success`](fn.guard_on_success.html).
+#[derive(Debug)]
+pub enum OnSuccess {}
+
+/// Only run the callback if the scope is left by a panic, see [`guard_on_unwind`](fn.guard_on_unwind.html).
+#[derive(Debug)]
+pub enum OnUnwind {}
+
+impl Strategy for Always {
+    #[inline(always)]
+    fn should_run() -> bool {
+        true
+    }
+}
+
+impl Strategy for OnSuccess {
+    #[inline]
+    fn should_run() -> bool {
+        !thread::panicking()
+    }
+}
+
+impl Strategy for OnUnwind {
+    #[inline]
+    fn should_run() -> bool {
+        thread::panicking()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{guard_on_success, guard_on_unwind, DropGuard, OnUnwind, Strategy};
+    use std::panic::catch_unwind;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    fn count<T>(_: T) {
+        COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generic_over_strategy<S: Strategy>(g: DropGuard<u8, fn(u8), S>) -> DropGuard<u8, fn(u8), S> {
+        g
+    }
+
+    #[test]
+    fn strategies_decide_at_drop() {
+        let r = catch_unwind(|| {
+            let _a = generic_over_strategy(guard_on_unwind(1, count as fn(u8)));
+            let _b = guard_on_success(1, count);
+            panic!("unwinding");
+        });
+        assert!(r.is_err());
+        assert_eq!(1, COUNT.load(Ordering::Relaxed));
+
+        let _ = DropGuard::<_, _, OnUnwind>::with_strategy(2, count);
+        assert_eq!(1, COUNT.load(Ordering::Relaxed));
+    }
+}
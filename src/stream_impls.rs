@@ -0,0 +1,79 @@
+//! Optional `Stream` support, enabled with the `futures` feature.
+
+use crate::{DropGuard, PinDropGuard, Strategy};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Poll a guarded stream directly, the callback gets whatever is left of it.
+///
+/// Like for futures the stream has to be `Unpin`, box it or use a
+/// [`PinDropGuard`](struct.PinDropGuard.html) otherwise.
+///
+/// ```
+/// use drop_guard::guard;
+/// use futures_util::stream::{self, Stream, StreamExt};
+/// use futures_util::FutureExt;
+///
+/// let mut left = 0;
+/// {
+///     let mut numbers = guard(stream::iter(1..=5), |rest| left = rest.size_hint().0);
+///     assert_eq!(Some(Some(1)), numbers.next().now_or_never());
+/// }
+/// assert_eq!(4, left);
+/// ```
+impl<T: Stream + Unpin, F: FnOnce(T), S: Strategy> Stream for DropGuard<T, F, S> {
+    type Item = T::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T::Item>> {
+        self.data_pin_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+/// Poll the guarded stream in place, it may be `!Unpin`.
+impl<T: Stream, F: FnOnce(Pin<&mut T>)> Stream for PinDropGuard<T, F> {
+    type Item = T::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T::Item>> {
+        self.as_pin_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{guard, pin_guard};
+    use futures_util::{stream, FutureExt, StreamExt};
+
+    #[test]
+    fn counts_yielded_items() {
+        let mut yielded = 0;
+        let mut seen = None;
+        {
+            let mut s = guard(stream::iter(0..10).inspect(|_| yielded += 1), |_| {
+                seen = Some(())
+            });
+            let firsts: Vec<_> = (&mut s).take(4).collect().now_or_never().unwrap();
+            assert_eq!(vec![0, 1, 2, 3], firsts);
+        }
+        assert_eq!(Some(()), seen);
+        assert_eq!(4, yielded);
+    }
+
+    #[test]
+    fn pinned_stream_passes_through() {
+        let s = pin_guard(
+            stream::iter(vec![1, 2]).then(|n| async move { n * 2 }),
+            |_| {},
+        );
+        let all: Vec<_> = Box::pin(s).collect().now_or_never().unwrap();
+        assert_eq!(vec![2, 4], all);
+    }
+}
@@ -0,0 +1,152 @@
+//! One thread that wakes futures whose deadline passed.
+
+use crate::report::report;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct State {
+    /// The deadlines of running timers and of dropped ones not yet cleared, earliest first.
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    /// The waker of every running timer, by id.
+    wakers: BTreeMap<u64, Option<Waker>>,
+    next_id: u64,
+    running: bool,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    deadlines: BinaryHeap::new(),
+    wakers: BTreeMap::new(),
+    next_id: 0,
+    running: false,
+});
+static CHANGED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Wakes the polling task once `budget` ran out, all timers share one thread.
+pub(crate) struct Timer {
+    id: u64,
+    deadline: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start(budget: Duration) -> Self {
+        // a budget too large for an `Instant` never runs out
+        let deadline = Instant::now()
+            .checked_add(budget)
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(60 * 60 * 24 * 365 * 100));
+        let mut state = lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.wakers.insert(id, None);
+        state.deadlines.push(Reverse((deadline, id)));
+        if !state.running {
+            match thread::Builder::new()
+                .name("drop_guard timer".to_string())
+                .spawn(run)
+            {
+                Ok(_) => state.running = true,
+                Err(e) => report(format_args!(
+                    "failed to start the drop_guard timer, a timeout of {:?} will not fire: {}",
+                    budget, e
+                )),
+            }
+        } else if state.deadlines.peek() == Some(&Reverse((deadline, id))) {
+            CHANGED.notify_one();
+        }
+        Timer { id, deadline }
+    }
+
+    pub(crate) fn poll_elapsed(&self, cx: &mut Context<'_>) -> Poll<()> {
+        // checked under the lock, the thread only wakes a deadline it saw pass
+        let mut state = lock();
+        if Instant::now() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            state.wakers.insert(self.id, Some(cx.waker().clone()));
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        lock().wakers.remove(&self.id);
+    }
+}
+
+fn run() {
+    let mut state = lock();
+    loop {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        while let Some(&Reverse((deadline, id))) = state.deadlines.peek() {
+            if !state.wakers.contains_key(&id) {
+                state.deadlines.pop();
+            } else if deadline <= now {
+                state.deadlines.pop();
+                expired.extend(state.wakers.get_mut(&id).and_then(Option::take));
+            } else {
+                break;
+            }
+        }
+        if !expired.is_empty() {
+            drop(state);
+            expired.into_iter().for_each(Waker::wake);
+            state = lock();
+            continue;
+        }
+        match state.deadlines.peek() {
+            Some(&Reverse((deadline, _))) => {
+                state = CHANGED
+                    .wait_timeout(state, deadline - now)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+            None => {
+                state.running = false;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wakes_each_running_timer_once() {
+        let woken = Arc::new(Count(AtomicUsize::new(0)));
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let timers: Vec<_> = (0..64)
+            .map(|i| Timer::start(Duration::from_millis(20 + i % 4)))
+            .collect();
+        let dropped = Timer::start(Duration::from_millis(10));
+        for timer in timers.iter().chain(Some(&dropped)) {
+            assert!(timer.poll_elapsed(&mut cx).is_pending());
+        }
+        drop(dropped);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(64, woken.0.load(Ordering::SeqCst));
+        assert!(timers.iter().all(|t| t.poll_elapsed(&mut cx).is_ready()));
+    }
+}
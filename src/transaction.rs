@@ -0,0 +1,584 @@
+//! Values that are rolled back unless they are committed.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::CleanupPool;
+use crate::{guard, DropGuard, GuardStack};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// Owns a value and rolls it back on drop unless [`commit`](#method.commit) was called.
+///
+/// ```
+/// use drop_guard::transaction;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// {
+///     let mut tx = transaction(vec!["create /tmp/a"], |steps| {
+///         log.borrow_mut().push(format!("undo {:?}", steps))
+///     })
+///     .on_commit(|steps| log.borrow_mut().push(format!("done {:?}", steps)))
+///     .on_commit(|_| log.borrow_mut().push("notify".to_string()));
+///     tx.push("create /tmp/b");
+///     let steps = tx.commit();
+///     assert_eq!(2, steps.len());
+/// }
+/// {
+///     let _tx = transaction(vec!["create /tmp/c"], |steps| {
+///         log.borrow_mut().push(format!("undo {:?}", steps))
+///     });
+/// }
+/// assert_eq!(
+///     vec![r#"done ["create /tmp/a", "create /tmp/b"]"#, "notify", r#"undo ["create /tmp/c"]"#],
+///     *log.borrow()
+/// );
+/// ```
+pub struct Transaction<'a, T, R: FnOnce(T), C: FnOnce(&mut T) = fn(&mut T)> {
+    // declared first so the steps of committed children roll back before this one
+    nested: GuardStack<'a>,
+    guard: DropGuard<T, R>,
+    commit: C,
+}
+
+/// Creates a [`Transaction`](struct.Transaction.html) that runs `rollback` unless it is committed.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn transaction<'a, T, R: FnOnce(T)>(data: T, rollback: R) -> Transaction<'a, T, R> {
+    Transaction {
+        nested: GuardStack::new(),
+        guard: guard(data, rollback),
+        commit: no_hooks,
+    }
+}
+
+fn no_hooks<T>(_: &mut T) {}
+
+impl<'a, T, R: FnOnce(T), C: FnOnce(&mut T)> Transaction<'a, T, R, C> {
+    /// Adds a hook that only runs on [`commit`](#method.commit), after the hooks added before it.
+    ///
+    /// Handy for actions that only make sense on success, like notifications or cache publishes.
+    #[must_use]
+    #[inline]
+    pub fn on_commit<G: FnOnce(&mut T)>(
+        self,
+        hook: G,
+    ) -> Transaction<'a, T, R, impl FnOnce(&mut T)> {
+        let previous = self.commit;
+        Transaction {
+            nested: self.nested,
+            guard: self.guard,
+            commit: move |data: &mut T| {
+                previous(data);
+                hook(data);
+            },
+        }
+    }
+
+    /// Runs the commit hooks in order and returns the value, the rollback is skipped.
+    #[inline]
+    pub fn commit(self) -> T {
+        self.nested.commit();
+        let (mut data, _rollback) = self.guard.into_parts();
+        (self.commit)(&mut data);
+        data
+    }
+
+    /// Rolls back right away instead of at the end of the scope.
+    #[inline]
+    pub fn rollback(self) {
+        drop(self.nested);
+        self.guard.trigger()
+    }
+
+    /// Starts a child transaction that rolls back `data` with `rollback`.
+    ///
+    /// If the child is dropped or rolled back, only its own steps are undone. Once it is
+    /// committed its rollback moves to this transaction and runs if this one rolls back,
+    /// before the own rollback of this transaction.
+    ///
+    /// ```
+    /// use drop_guard::transaction;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// {
+    ///     let mut base = transaction("base", |layer| log.borrow_mut().push(layer));
+    ///     base.nested("runtime", |layer| log.borrow_mut().push(layer)).commit();
+    ///     {
+    ///         let _plugin = base.nested("plugin", |layer| log.borrow_mut().push(layer));
+    ///         // the plugin failed to install
+    ///     }
+    ///     assert_eq!(vec!["plugin"], *log.borrow());
+    ///     // the base fails as well
+    /// }
+    /// assert_eq!(vec!["plugin", "runtime", "base"], *log.borrow());
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn nested<U, S: FnOnce(U) + 'a>(
+        &mut self,
+        data: U,
+        rollback: S,
+    ) -> NestedTransaction<'_, 'a, U, S>
+    where
+        U: 'a,
+    {
+        NestedTransaction {
+            inner: transaction(data, rollback),
+            parent: &mut self.nested,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a, T, R, C> Transaction<'a, T, R, C>
+where
+    T: Send + 'static,
+    R: FnOnce(T) + Send + 'static,
+    C: FnOnce(&mut T),
+{
+    /// Stops waiting for the rollback after `budget`, see [`DropGuard::with_deadline`](struct.DropGuard.html#method.with_deadline).
+    ///
+    /// ```
+    /// use drop_guard::transaction;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let start = Instant::now();
+    /// {
+    ///     let _tx = transaction("lease-3", |_| std::thread::sleep(Duration::from_secs(5)))
+    ///         .with_deadline(Duration::from_millis(10));
+    /// }
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn with_deadline(self, budget: Duration) -> Transaction<'a, T, impl FnOnce(T), C> {
+        Transaction {
+            nested: self.nested,
+            guard: self.guard.with_deadline(budget),
+            commit: self.commit,
+        }
+    }
+}
+
+impl<T, R: FnOnce(T), C: FnOnce(&mut T)> Deref for Transaction<'_, T, R, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T, R: FnOnce(T), C: FnOnce(&mut T)> DerefMut for Transaction<'_, T, R, C> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: fmt::Debug, R: FnOnce(T), C: FnOnce(&mut T)> fmt::Debug for Transaction<'_, T, R, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("data", &**self)
+            .finish()
+    }
+}
+
+/// A child of a [`Transaction`](struct.Transaction.html), see [`Transaction::nested`](struct.Transaction.html#method.nested).
+pub struct NestedTransaction<'p, 'a, T, R: FnOnce(T)> {
+    inner: Transaction<'a, T, R>,
+    parent: &'p mut GuardStack<'a>,
+}
+
+impl<'a, T: 'a, R: FnOnce(T) + 'a> NestedTransaction<'_, 'a, T, R> {
+    /// Hands the rollback of this child and of its committed children to the parent.
+    pub fn commit(self) {
+        let Transaction {
+            mut nested, guard, ..
+        } = self.inner;
+        let (data, rollback) = guard.into_parts();
+        self.parent.push(move || rollback(data));
+        self.parent.append(&mut nested);
+    }
+
+    /// Rolls back the steps of this child right away, the parent is left alone.
+    pub fn rollback(self) {
+        self.inner.rollback()
+    }
+
+    /// Starts a child of this child, see [`Transaction::nested`](struct.Transaction.html#method.nested).
+    #[must_use]
+    #[track_caller]
+    pub fn nested<U, S: FnOnce(U) + 'a>(
+        &mut self,
+        data: U,
+        rollback: S,
+    ) -> NestedTransaction<'_, 'a, U, S>
+    where
+        U: 'a,
+    {
+        self.inner.nested(data, rollback)
+    }
+}
+
+impl<T, R: FnOnce(T)> Deref for NestedTransaction<'_, '_, T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, R: FnOnce(T)> DerefMut for NestedTransaction<'_, '_, T, R> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: fmt::Debug, R: FnOnce(T)> fmt::Debug for NestedTransaction<'_, '_, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NestedTransaction")
+            .field("data", &**self)
+            .finish()
+    }
+}
+
+/// Ties rollbacks to the result of a closure, see [`run`](#method.run).
+#[derive(Debug, Default)]
+pub struct TransactionScope<'a> {
+    rollbacks: GuardStack<'a>,
+}
+
+impl<'a> TransactionScope<'a> {
+    /// Runs `body`, its rollbacks are dropped on `Ok` and run in reverse order on `Err` or a panic.
+    ///
+    /// ```
+    /// use drop_guard::TransactionScope;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let result: Result<(), String> = TransactionScope::run(|scope| {
+    ///     log.borrow_mut().push("insert user");
+    ///     scope.defer_rollback(|| log.borrow_mut().push("delete user"));
+    ///     "x".parse::<u8>().map_err(|e| e.to_string())?;
+    ///     Ok(())
+    /// });
+    /// assert!(result.is_err());
+    /// assert_eq!(vec!["insert user", "delete user"], *log.borrow());
+    /// ```
+    pub fn run<R, E, B>(body: B) -> Result<R, E>
+    where
+        B: FnOnce(&mut TransactionScope<'a>) -> Result<R, E>,
+    {
+        let mut scope = TransactionScope::default();
+        let result = body(&mut scope);
+        if result.is_ok() {
+            scope.rollbacks.commit();
+        }
+        result
+    }
+
+    /// Runs `body` as a child transaction.
+    ///
+    /// On `Err` or a panic only the rollbacks of the child run. On `Ok` they move to this
+    /// scope and still run if this scope fails later.
+    ///
+    /// ```
+    /// use drop_guard::TransactionScope;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let result: Result<(), &str> = TransactionScope::run(|scope| {
+    ///     scope.defer_rollback(|| log.borrow_mut().push("uninstall base"));
+    ///     let plugin: Result<(), &str> = scope.nested(|child| {
+    ///         child.defer_rollback(|| log.borrow_mut().push("uninstall plugin"));
+    ///         Err("plugin broken")
+    ///     });
+    ///     assert!(plugin.is_err());
+    ///     scope.nested(|child| {
+    ///         child.defer_rollback(|| log.borrow_mut().push("uninstall docs"));
+    ///         Ok(())
+    ///     })?;
+    ///     Err("disk full")
+    /// });
+    /// assert!(result.is_err());
+    /// assert_eq!(
+    ///     vec!["uninstall plugin", "uninstall docs", "uninstall base"],
+    ///     *log.borrow()
+    /// );
+    /// ```
+    pub fn nested<R, E, B>(&mut self, body: B) -> Result<R, E>
+    where
+        B: FnOnce(&mut TransactionScope<'a>) -> Result<R, E>,
+    {
+        let mut child = TransactionScope::default();
+        let result = body(&mut child);
+        if result.is_ok() {
+            self.rollbacks.append(&mut child.rollbacks);
+        }
+        result
+    }
+
+    /// Runs `body` like [`run`](#method.run), but its rollbacks run in parallel on up to `threads` threads.
+    ///
+    /// The rollbacks are collected in a [`CleanupPool`](struct.CleanupPool.html), use it
+    /// for many independent compensations like deleting uploaded files.
+    ///
+    /// ```
+    /// use drop_guard::TransactionScope;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let deleted = AtomicUsize::new(0);
+    /// let result: Result<(), &str> = TransactionScope::run_parallel(4, |rollbacks| {
+    ///     for _file in 0..16 {
+    ///         // upload the file ...
+    ///         rollbacks.push(|| {
+    ///             deleted.fetch_add(1, Ordering::SeqCst);
+    ///         });
+    ///     }
+    ///     Err("quota exceeded")
+    /// });
+    /// assert!(result.is_err());
+    /// assert_eq!(16, deleted.load(Ordering::SeqCst));
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_parallel<R, E, B>(threads: usize, body: B) -> Result<R, E>
+    where
+        B: FnOnce(&mut CleanupPool<'a>) -> Result<R, E>,
+    {
+        let mut rollbacks = CleanupPool::new(threads);
+        let result = body(&mut rollbacks);
+        if result.is_ok() {
+            rollbacks.commit();
+        }
+        result
+    }
+
+    /// Runs `body` as a child transaction whose rollbacks run in parallel, see
+    /// [`nested`](#method.nested) and [`run_parallel`](#method.run_parallel).
+    ///
+    /// On `Ok` the rollbacks become one step of this scope, they still run in parallel.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn nested_parallel<R, E, B>(&mut self, threads: usize, body: B) -> Result<R, E>
+    where
+        B: FnOnce(&mut CleanupPool<'a>) -> Result<R, E>,
+    {
+        let mut rollbacks = CleanupPool::new(threads);
+        let result = body(&mut rollbacks);
+        if result.is_ok() {
+            // committing this scope drops the step without running it, which commits the pool
+            let child = guard(rollbacks, CleanupPool::commit);
+            self.rollbacks.push(move || drop(child.into_parts().0));
+        }
+        result
+    }
+
+    /// Registers a rollback that runs if the body of [`run`](#method.run) fails.
+    #[track_caller]
+    pub fn defer_rollback<F: FnOnce() + 'a>(&mut self, rollback: F) {
+        self.rollbacks.push(rollback)
+    }
+}
+
+/// Runs the `do` of each step in order and returns a `Result`.
+///
+/// A step binds the `Ok` value of its `do` to its name, later steps and the result after
+/// `=>` can use it. If a step fails, returns an `Err` or panics, the `undo`s of the earlier
+/// steps run in reverse order and receive their values. Until the end, a name refers to a
+/// guard of the value, so later steps reach the value through `Deref`.
+///
+/// ```
+/// use drop_guard::transaction;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// let create = |what: String| -> Result<String, String> {
+///     if what.starts_with("port") {
+///         return Err(format!("{} in use", what));
+///     }
+///     log.borrow_mut().push(format!("create {}", what));
+///     Ok(what)
+/// };
+///
+/// let install: Result<(String, String), String> = transaction! {
+///     step binary { do => create("/usr/bin/app".to_string()), undo => |b| log.borrow_mut().push(format!("delete {}", b)) },
+///     step user { do => create(format!("user for {}", *binary)), undo => |u| log.borrow_mut().push(format!("delete {}", u)) },
+///     => (binary, user)
+/// };
+/// assert_eq!(Ok(("/usr/bin/app".to_string(), "user for /usr/bin/app".to_string())), install);
+///
+/// let serve: Result<(), String> = transaction! {
+///     step config { do => create("/etc/app.conf".to_string()), undo => |c| log.borrow_mut().push(format!("delete {}", c)) },
+///     step _port { do => create("port 80".to_string()), undo => |_| unreachable!() },
+/// };
+/// assert_eq!(Err("port 80 in use".to_string()), serve);
+/// assert_eq!(
+///     vec!["create /etc/app.conf", "delete /etc/app.conf"],
+///     log.borrow()[2..]
+/// );
+/// ```
+#[macro_export]
+macro_rules! transaction {
+    (@result) => {
+        ()
+    };
+    (@result $result:expr) => {
+        $result
+    };
+    ($(step $name:ident { do => $action:expr, undo => |$value:pat_param| $undo:expr $(,)? }),* $(,)? $(=> $result:expr $(,)?)?) => {
+        $crate::TransactionScope::run(|_| {
+            $(
+                #[allow(unused_mut)]
+                let mut $name = $crate::guard($action?, |$value| {
+                    $undo;
+                });
+            )*
+            $(
+                #[allow(unused_variables)]
+                let $name = $crate::DropGuard::into_parts($name).0;
+            )*
+            Ok($crate::transaction!(@result $($result)?))
+        })
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_rollback() {
+        let mut rolled_back = None;
+        let mut committed = false;
+        transaction(7, |v| rolled_back = Some(v))
+            .on_commit(|_| committed = true)
+            .rollback();
+        assert_eq!(Some(7), rolled_back);
+        assert!(!committed);
+    }
+
+    #[test]
+    fn commit_without_callback() {
+        let mut rolled_back = false;
+        assert_eq!(7, transaction(7, |_| rolled_back = true).commit());
+        assert!(!rolled_back);
+    }
+
+    #[test]
+    fn scope_commits_on_ok() {
+        let mut rolled_back = false;
+        let result: Result<u8, ()> = TransactionScope::run(|scope| {
+            scope.defer_rollback(|| rolled_back = true);
+            Ok(4)
+        });
+        assert_eq!(Ok(4), result);
+        assert!(!rolled_back);
+    }
+
+    #[test]
+    fn committed_child_is_kept_with_parent() {
+        let mut rolled_back = false;
+        let result: Result<(), ()> = TransactionScope::run(|scope| {
+            scope.nested(|child| {
+                child.defer_rollback(|| rolled_back = true);
+                Ok(())
+            })
+        });
+        assert!(result.is_ok());
+        assert!(!rolled_back);
+    }
+
+    #[test]
+    fn macro_commits() {
+        let undone = std::cell::Cell::new(false);
+        let result: Result<u8, ()> = transaction! {
+            step one { do => Ok(1), undo => |_| undone.set(true) },
+            step two { do => Ok(*one + 1), undo => |_| undone.set(true) },
+            => one + two
+        };
+        assert_eq!(Ok(3), result);
+        assert!(!undone.get());
+    }
+
+    #[test]
+    fn macro_passes_values_to_undo() {
+        let undone = std::cell::RefCell::new(Vec::new());
+        let result: Result<(), &str> = transaction! {
+            step a { do => Ok("a"), undo => |v| undone.borrow_mut().push(v) },
+            step b { do => Ok(format!("{}b", *a)), undo => |v| undone.borrow_mut().push(v.leak()) },
+            step _c { do => Err::<(), _>("c failed"), undo => |_| unreachable!() },
+        };
+        assert_eq!(Err("c failed"), result);
+        assert_eq!(vec!["ab", "a"], *undone.borrow());
+    }
+
+    #[test]
+    fn parallel_child_rolls_back_with_parent() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let undone = AtomicUsize::new(0);
+        let result: Result<(), ()> = TransactionScope::run(|scope| {
+            scope.nested_parallel(2, |rollbacks| {
+                for _ in 0..4 {
+                    rollbacks.push(|| {
+                        undone.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+                Ok::<(), ()>(())
+            })?;
+            assert_eq!(0, undone.load(Ordering::SeqCst));
+            Err(())
+        });
+        assert!(result.is_err());
+        assert_eq!(4, undone.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn parallel_child_commits_with_parent() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let undone = AtomicUsize::new(0);
+        let result: Result<(), ()> = TransactionScope::run(|scope| {
+            scope.nested_parallel(2, |rollbacks| {
+                rollbacks.push(|| {
+                    undone.fetch_add(1, Ordering::SeqCst);
+                });
+                Ok(())
+            })
+        });
+        assert!(result.is_ok());
+        assert_eq!(0, undone.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn committed_child_rolls_back_with_parent() {
+        let log = std::cell::RefCell::new(Vec::new());
+        {
+            let mut parent = transaction(1, |v| log.borrow_mut().push(v));
+            let mut child = parent.nested(2, |v| log.borrow_mut().push(v));
+            child.nested(3, |v| log.borrow_mut().push(v)).commit();
+            child.commit();
+            parent.nested(4, |v| log.borrow_mut().push(v)).rollback();
+            assert_eq!(vec![4], *log.borrow());
+        }
+        assert_eq!(vec![4, 3, 2, 1], *log.borrow());
+    }
+
+    #[test]
+    fn committed_child_commits_with_parent() {
+        let rolled_back = std::cell::Cell::new(false);
+        let mut parent = transaction(1, |_| rolled_back.set(true));
+        parent.nested(2, |_| rolled_back.set(true)).commit();
+        assert_eq!(1, parent.commit());
+        assert!(!rolled_back.get());
+    }
+
+    #[test]
+    fn hooks_run_in_order() {
+        let tx = transaction(Vec::new(), |_| panic!("rolled back"))
+            .on_commit(|v| v.push(1))
+            .on_commit(|v| v.push(2));
+        assert_eq!(vec![1, 2], tx.commit());
+    }
+}
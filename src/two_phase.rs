@@ -0,0 +1,150 @@
+//! A two-phase commit over several participants.
+
+use crate::GuardStack;
+use std::fmt;
+
+type Prepare<'a, E> = Box<dyn FnOnce() -> Result<(), E> + 'a>;
+
+struct Participant<'a, E> {
+    prepare: Prepare<'a, E>,
+    commit: Box<dyn FnOnce() + 'a>,
+    abort: Box<dyn FnOnce() + 'a>,
+}
+
+/// Commits all participants only if every one of them prepared successfully.
+///
+/// Otherwise, or if a prepare panics, every participant is aborted in reverse order.
+///
+/// ```
+/// use drop_guard::TwoPhaseCommit;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// let result = TwoPhaseCommit::new()
+///     .participant(
+///         || Ok(()),
+///         || log.borrow_mut().push("dns committed"),
+///         || log.borrow_mut().push("dns aborted"),
+///     )
+///     .participant(
+///         || Err("firewall config invalid"),
+///         || log.borrow_mut().push("firewall committed"),
+///         || log.borrow_mut().push("firewall aborted"),
+///     )
+///     .run();
+///
+/// assert_eq!(Err("firewall config invalid"), result);
+/// assert_eq!(vec!["firewall aborted", "dns aborted"], *log.borrow());
+/// ```
+pub struct TwoPhaseCommit<'a, E> {
+    participants: Vec<Participant<'a, E>>,
+}
+
+impl<'a, E> TwoPhaseCommit<'a, E> {
+    /// Creates a coordinator without participants.
+    pub fn new() -> TwoPhaseCommit<'a, E> {
+        TwoPhaseCommit {
+            participants: Vec::new(),
+        }
+    }
+
+    /// Adds a participant with its three phases.
+    #[must_use]
+    pub fn participant<P, C, A>(mut self, prepare: P, commit: C, abort: A) -> TwoPhaseCommit<'a, E>
+    where
+        P: FnOnce() -> Result<(), E> + 'a,
+        C: FnOnce() + 'a,
+        A: FnOnce() + 'a,
+    {
+        self.participants.push(Participant {
+            prepare: Box::new(prepare),
+            commit: Box::new(commit),
+            abort: Box::new(abort),
+        });
+        self
+    }
+
+    /// Prepares all participants in order, then commits them all or aborts them all.
+    ///
+    /// Returns the error of the first prepare that failed, the later ones are not prepared.
+    pub fn run(self) -> Result<(), E> {
+        let mut aborts = GuardStack::new();
+        let mut prepares = Vec::with_capacity(self.participants.len());
+        let mut commits = Vec::with_capacity(self.participants.len());
+        for participant in self.participants {
+            aborts.push(participant.abort);
+            prepares.push(participant.prepare);
+            commits.push(participant.commit);
+        }
+
+        for prepare in prepares {
+            prepare()?;
+        }
+        aborts.commit();
+        for commit in commits {
+            commit();
+        }
+        Ok(())
+    }
+}
+
+impl<E> Default for TwoPhaseCommit<'_, E> {
+    fn default() -> Self {
+        TwoPhaseCommit::new()
+    }
+}
+
+impl<E> fmt::Debug for TwoPhaseCommit<'_, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TwoPhaseCommit")
+            .field("participants", &self.participants.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn commits_in_order() {
+        let log = RefCell::new(Vec::new());
+        let result: Result<(), ()> = TwoPhaseCommit::new()
+            .participant(
+                || Ok(()),
+                || log.borrow_mut().push(1),
+                || log.borrow_mut().push(-1),
+            )
+            .participant(
+                || Ok(()),
+                || log.borrow_mut().push(2),
+                || log.borrow_mut().push(-2),
+            )
+            .run();
+        assert!(result.is_ok());
+        assert_eq!(vec![1, 2], *log.borrow());
+    }
+
+    #[test]
+    fn panicking_prepare_aborts() {
+        let log = RefCell::new(Vec::new());
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            TwoPhaseCommit::<()>::new()
+                .participant(
+                    || Ok(()),
+                    || log.borrow_mut().push(1),
+                    || log.borrow_mut().push(-1),
+                )
+                .participant(
+                    || panic!("prepare failed"),
+                    || {},
+                    || log.borrow_mut().push(-2),
+                )
+                .run()
+        }));
+        assert!(r.is_err());
+        assert_eq!(vec![-2, -1], *log.borrow());
+    }
+}
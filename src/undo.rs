@@ -0,0 +1,157 @@
+//! An undo history of reversible operations.
+
+use std::fmt;
+
+struct Operation<'a> {
+    apply: Box<dyn FnMut() + 'a>,
+    unapply: Box<dyn FnMut() + 'a>,
+}
+
+/// What an [`UndoStack`](struct.UndoStack.html) does with the applied operations when it is dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UndoOnDrop {
+    /// Undo all applied operations, the latest first.
+    #[default]
+    UndoAll,
+    /// Keep the applied operations.
+    Keep,
+}
+
+/// Applies reversible operations and can undo and redo them.
+///
+/// ```
+/// use drop_guard::{UndoOnDrop, UndoStack};
+/// use std::cell::Cell;
+///
+/// let volume = Cell::new(5);
+/// let mut history = UndoStack::new(UndoOnDrop::Keep);
+/// history.push(|| volume.set(volume.get() + 1), || volume.set(volume.get() - 1));
+/// history.push(|| volume.set(volume.get() * 2), || volume.set(volume.get() / 2));
+/// assert_eq!(12, volume.get());
+///
+/// history.undo();
+/// assert_eq!(6, volume.get());
+/// history.redo();
+/// assert_eq!(12, volume.get());
+/// ```
+pub struct UndoStack<'a> {
+    done: Vec<Operation<'a>>,
+    undone: Vec<Operation<'a>>,
+    on_drop: UndoOnDrop,
+}
+
+impl<'a> UndoStack<'a> {
+    /// Creates an empty history.
+    pub fn new(on_drop: UndoOnDrop) -> UndoStack<'a> {
+        UndoStack {
+            done: Vec::new(),
+            undone: Vec::new(),
+            on_drop,
+        }
+    }
+
+    /// Runs `apply` and records it, the operations that could be redone are dropped.
+    pub fn push<A, U>(&mut self, mut apply: A, unapply: U)
+    where
+        A: FnMut() + 'a,
+        U: FnMut() + 'a,
+    {
+        apply();
+        self.undone.clear();
+        self.done.push(Operation {
+            apply: Box::new(apply),
+            unapply: Box::new(unapply),
+        });
+    }
+
+    /// Unapplies the latest operation, returns `false` if there was none.
+    pub fn undo(&mut self) -> bool {
+        match self.done.pop() {
+            Some(mut op) => {
+                (op.unapply)();
+                self.undone.push(op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the latest undone operation again, returns `false` if there was none.
+    pub fn redo(&mut self) -> bool {
+        match self.undone.pop() {
+            Some(mut op) => {
+                (op.apply)();
+                self.done.push(op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether there is an operation to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    /// Whether there is an operation to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Changes what happens to the applied operations on drop.
+    pub fn set_on_drop(&mut self, on_drop: UndoOnDrop) {
+        self.on_drop = on_drop;
+    }
+}
+
+impl fmt::Debug for UndoStack<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UndoStack")
+            .field("done", &self.done.len())
+            .field("undone", &self.undone.len())
+            .field("on_drop", &self.on_drop)
+            .finish()
+    }
+}
+
+impl Drop for UndoStack<'_> {
+    fn drop(&mut self) {
+        if self.on_drop == UndoOnDrop::UndoAll {
+            while self.undo() {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn undo_all_on_drop() {
+        let log = RefCell::new(Vec::new());
+        {
+            let mut history = UndoStack::new(UndoOnDrop::UndoAll);
+            history.push(
+                || log.borrow_mut().push("a"),
+                || log.borrow_mut().push("-a"),
+            );
+            history.push(
+                || log.borrow_mut().push("b"),
+                || log.borrow_mut().push("-b"),
+            );
+        }
+        assert_eq!(vec!["a", "b", "-b", "-a"], *log.borrow());
+    }
+
+    #[test]
+    fn push_clears_redo() {
+        let mut history = UndoStack::new(UndoOnDrop::Keep);
+        history.push(|| {}, || {});
+        assert!(history.undo());
+        assert!(history.can_redo());
+        history.push(|| {}, || {});
+        assert!(!history.can_redo());
+        assert!(!history.redo());
+    }
+}
@@ -0,0 +1,182 @@
+//! What happens if a callback panics.
+
+use crate::report::{report, Hook};
+use crate::{events, name};
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe, Location};
+use std::process;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+
+/// Receives the payload of a callback panic that is not propagated,
+/// together with the creation site of its guard.
+pub type UnwindPanicHandler = fn(Box<dyn Any + Send>, &'static Location<'static>);
+
+static HANDLER: Hook<UnwindPanicHandler> = Hook::new();
+
+/// Decides what happens when a callback panics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PanicPolicy {
+    /// Print the panic and abort the process.
+    Abort,
+    /// Hand the panic to the [`UnwindPanicHandler`](type.UnwindPanicHandler.html) and carry on.
+    LogAndContinue,
+    /// Let the panic continue out of the drop.
+    ///
+    /// A callback that runs during unwinding cannot propagate, so it is logged instead.
+    #[default]
+    Propagate,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(PanicPolicy::Propagate as u8);
+
+/// Sets the [`PanicPolicy`](enum.PanicPolicy.html) of all guards without their own.
+///
+/// ```
+/// use drop_guard::{guard, set_panic_policy, PanicPolicy};
+///
+/// set_panic_policy(PanicPolicy::LogAndContinue);
+/// {
+///     let _g = guard(1, |_| panic!("cleanup failed"));
+/// }
+/// println!("still shutting down");
+/// ```
+pub fn set_panic_policy(policy: PanicPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+    if policy == PanicPolicy::Propagate {
+        events::disable(events::POLICY);
+    } else {
+        events::enable(events::POLICY);
+    }
+}
+
+fn global_policy() -> PanicPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        0 => PanicPolicy::Abort,
+        1 => PanicPolicy::LogAndContinue,
+        _ => PanicPolicy::Propagate,
+    }
+}
+
+/// Routes panics of callbacks that are not propagated to `handler`.
+///
+/// A second panic escaping a destructor while unwinding aborts the process.
+/// So the guard catches it and hands it to the handler instead,
+/// by default it is printed to stderr and the unwinding continues.
+/// The same goes for panics under [`PanicPolicy::LogAndContinue`](enum.PanicPolicy.html).
+///
+/// ```
+/// use drop_guard::{guard, set_unwind_panic_handler};
+/// use std::panic::catch_unwind;
+///
+/// set_unwind_panic_handler(|_payload, location| {
+///     eprintln!("cleanup of the guard from {} failed too", location)
+/// });
+///
+/// let result = catch_unwind(|| {
+///     let _g = guard(1, |_| panic!("cleanup failed"));
+///     panic!("work failed");
+/// });
+/// assert!(result.is_err());
+/// ```
+pub fn set_unwind_panic_handler(handler: UnwindPanicHandler) {
+    HANDLER.set(handler)
+}
+
+/// Runs the callback `f` of the guard created at `location`.
+///
+/// A panic follows the guard's own `policy`, or the global one if it has none.
+#[inline]
+pub(crate) fn run(
+    location: &'static Location<'static>,
+    policy: Option<PanicPolicy>,
+    f: impl FnOnce(),
+) {
+    let policy = policy.unwrap_or_else(global_policy);
+    if policy == PanicPolicy::Propagate && !thread::panicking() {
+        // nothing to catch, a panic just unwinds past the guard
+        return f();
+    }
+    let payload = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(()) => return,
+        Err(payload) => payload,
+    };
+    match policy {
+        PanicPolicy::Abort => {
+            report(format_args!(
+                "the callback of the {} panicked, aborting: {}",
+                name::failed(location),
+                message(&*payload)
+            ));
+            process::abort()
+        }
+        _ => match HANDLER.get() {
+            Some(handler) => handler(payload, location),
+            None => report(format_args!(
+                "the callback of the {} panicked: {}",
+                name::failed(location),
+                message(&*payload)
+            )),
+        },
+    }
+}
+
+/// Extracts the message of a panic payload if there is one.
+pub(crate) fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{guard, PanicPolicy};
+    use std::panic::catch_unwind;
+
+    #[test]
+    fn second_panic_does_not_abort() {
+        let mut after = false;
+        let r = catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _later = guard(1, |_| after = true);
+            let _g = guard(1, |_| panic!("cleanup failed"));
+            panic!("work failed");
+        }));
+        assert!(r.is_err());
+        assert!(after);
+    }
+
+    #[test]
+    fn per_guard_policy() {
+        let mut after = false;
+        {
+            let _later = guard(1, |_| after = true);
+            let _g = guard(1, |_| panic!("cleanup failed"))
+                .with_panic_policy(PanicPolicy::LogAndContinue);
+        }
+        assert!(after);
+
+        let r = catch_unwind(|| {
+            let _g =
+                guard(1, |_| panic!("cleanup failed")).with_panic_policy(PanicPolicy::Propagate);
+        });
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn policy_does_not_leak_from_into_parts() {
+        let (_, func) = guard(1, |_| panic!("cleanup failed"))
+            .with_panic_policy(PanicPolicy::Abort)
+            .into_parts();
+        assert!(catch_unwind(|| func(1)).is_err());
+
+        // an unrelated guard still follows the global policy
+        let r = catch_unwind(|| {
+            let _g = guard(1, |_| panic!("cleanup failed"));
+        });
+        assert!(r.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! A few threads kept around for work the dropping thread must not wait for.
+
+use crate::report::report;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How long a worker waits for a new job before it ends.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct State {
+    jobs: VecDeque<Job>,
+    idle: usize,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    jobs: VecDeque::new(),
+    idle: 0,
+});
+static WORK: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs `job` on an idle worker, a new one is only started if all are busy.
+///
+/// If no thread can be started, a queued job runs on the calling thread instead.
+/// A panic of the job is swallowed, catch it in the job to pass it on. Never panics itself.
+pub(crate) fn execute<F: FnOnce() + Send + 'static>(job: F) {
+    let mut state = lock();
+    state.jobs.push_back(Box::new(job));
+    if state.idle >= state.jobs.len() {
+        WORK.notify_one();
+    } else {
+        drop(state);
+        // without a new thread the job waits for a busy worker
+        let spawned = thread::Builder::new()
+            .name("drop_guard worker".to_string())
+            .spawn(work);
+        if let Err(e) = spawned {
+            report(format_args!(
+                "failed to start a drop_guard worker, running the cleanup on the current thread: {}",
+                e
+            ));
+            let job = lock().jobs.pop_back();
+            if let Some(job) = job {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+        }
+    }
+}
+
+fn work() {
+    let mut state = lock();
+    loop {
+        if let Some(job) = state.jobs.pop_front() {
+            drop(state);
+            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            state = lock();
+            continue;
+        }
+        state.idle += 1;
+        let (woken, wait) = WORK
+            .wait_timeout(state, IDLE_TIMEOUT)
+            .unwrap_or_else(|e| e.into_inner());
+        state = woken;
+        state.idle -= 1;
+        if wait.timed_out() && state.jobs.is_empty() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn stuck_job_does_not_hold_up_others() {
+        let (tx, rx) = mpsc::channel();
+        execute(|| thread::sleep(Duration::from_secs(2)));
+        for i in 0..4 {
+            let tx = tx.clone();
+            execute(move || tx.send(i).unwrap());
+        }
+        let mut done: Vec<_> = (0..4)
+            .map(|_| rx.recv_timeout(Duration::from_secs(1)).unwrap())
+            .collect();
+        done.sort();
+        assert_eq!(vec![0, 1, 2, 3], done);
+    }
+}